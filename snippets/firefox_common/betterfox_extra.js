@@ -1,7 +1,3 @@
-// PREF: revert back to Standard ETP
-user_pref("browser.contentblocking.category", "standard");
-// PREF: allow websites to ask you for your location
-user_pref("permissions.default.geo", 0);
 // PREF: remove default Top Sites (Facebook, Twitter, etc.)
 // This does not block you from adding your own.
 user_pref("browser.newtabpage.activity-stream.default.sites", "");
@@ -0,0 +1,7 @@
+// PREF: Strict ETP, blocks cross-site cookies, trackers, cryptominers and fingerprinters
+user_pref("browser.contentblocking.category", "strict");
+// PREF: HTTPS-Only Mode, asks before loading a site over HTTP
+user_pref("dom.security.https_only_mode", true);
+user_pref("dom.security.https_only_mode_ever_enabled", true);
+// PREF: fingerprinting protection in normal windows too, not only private ones
+user_pref("privacy.fingerprintingProtection", true);
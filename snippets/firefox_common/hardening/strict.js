@@ -0,0 +1,13 @@
+// PREF: resist fingerprinting, every Firefox reports the same screen, fonts and time zone
+// NOTE: some sites break or show the wrong time
+user_pref("privacy.resistFingerprinting", true);
+// PREF: clear cookies, site data and cache on shutdown
+// Sites with a cookie exception (--keep-cookies) stay logged in
+user_pref("privacy.sanitize.sanitizeOnShutdown", true);
+user_pref("privacy.clearOnShutdown_v2.cookiesAndStorage", true);
+user_pref("privacy.clearOnShutdown_v2.cache", true);
+user_pref("privacy.clearOnShutdown_v2.browsingHistoryAndDownloads", false);
+user_pref("privacy.clearOnShutdown.cookies", true);
+user_pref("privacy.clearOnShutdown.offlineApps", true);
+user_pref("privacy.clearOnShutdown.cache", true);
+user_pref("privacy.clearOnShutdown.history", false);
@@ -0,0 +1,2 @@
+// PREF: revert back to Standard ETP
+user_pref("browser.contentblocking.category", "standard");
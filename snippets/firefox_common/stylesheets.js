@@ -0,0 +1,2 @@
+// Load userChrome.css and userContent.css from the profile's chrome folder
+user_pref("toolkit.legacyUserProfileCustomizations.stylesheets", true);
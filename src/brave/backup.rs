@@ -0,0 +1,50 @@
+use crate::{
+    browser::{installation::Installation, profile::BrowserProfile}, util::{archive, backups::Slot, logging::success}
+};
+use color_eyre::eyre::WrapErr;
+use std::{fs, path::Path, sync::LazyLock};
+use tracing::{debug, warn};
+
+static DEFAULT_CHROMIUM_SKIP: LazyLock<Vec<&str>> = LazyLock::new(|| {
+    include_str!("../../snippets/brave/skipped_files").lines().filter(|l| !l.is_empty()).collect()
+});
+
+// The files in the data folder we rewrite, every profile gets its own zip
+pub fn backup_data_folder(
+    installation: &Installation,
+    data_folder: &Path
+) -> color_eyre::Result<()> {
+    let slot = Slot::profile(installation, data_folder);
+
+    let local_state_path = data_folder.join("Local State");
+    let local_state = fs::read(&local_state_path).wrap_err("Failed to read Local State")?;
+    slot.save("Local State", local_state, Some(&local_state_path))?;
+
+    // This is less important to have a backup of, so warn but continue
+    let feature_state_path = data_folder.join("ChromeFeatureState");
+    match fs::read(&feature_state_path) {
+        Ok(feature_state) => {
+            let saved = slot.save("ChromeFeatureState", feature_state, Some(&feature_state_path));
+            if let Err(why) = saved {
+                warn!(err = ?why, "Failed to backup Brave feature state file, continuing anyway");
+            }
+        }
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => {
+            debug!("ChromeFeatureState does not exist, nothing to backup");
+        }
+        Err(why) => {
+            warn!(err = ?why, "Failed to backup Brave feature state file, continuing anyway");
+        }
+    }
+
+    success(&format!("Backed up Brave's Local State in {}", data_folder.display()));
+    Ok(())
+}
+
+pub fn backup_profile(
+    installation: &Installation,
+    profile: &BrowserProfile
+) -> color_eyre::Result<()> {
+    // Caches are rebuilt by the browser and would make up most of the zip
+    archive::backup_profile(installation, profile, &DEFAULT_CHROMIUM_SKIP)
+}
@@ -1,52 +1,60 @@
 use crate::{brave::{
-    resources, resources::{DISABLED_FEATURES, REMOVE_ENABLED_FEATURES}
-}, s, util::timestamp};
+    features::KnownFeatures, resources, resources::{DISABLED_FEATURES, REMOVE_ENABLED_FEATURES}
+}, s};
 use color_eyre::eyre::{ContextCompat, WrapErr};
 use resources::replace_symbols;
 use serde_json::{json, Map, Value};
 use std::{fs, path::Path};
-use tracing::{debug, warn};
-use crate::util::args;
-use crate::util::logging::success;
+use tracing::debug;
+use crate::util::transaction::Transaction;
 
-pub fn chrome_feature_state(root: &Path) -> color_eyre::Result<()> {
+pub fn chrome_feature_state(
+    root: &Path,
+    known: Option<&KnownFeatures>,
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
     let path = root.join("ChromeFeatureState");
     if !path.exists() {
         debug!(path = %path.display(), "ChromeFeatureState does not exist, creating it");
     }
 
-    if args().backup && path.exists() {
-        let backup = root.join(format!("ChromeFeatureState-{}", timestamp())).with_extension("bak");
-        // This is less important to have a backup of, so warn but continue
-        match fs::copy(&path, &backup) {
-            Ok(_) => {
-                success("Backed up Brave feature state file");
-                debug!("Backup dir: {}", backup.display());
-            }
-            Err(why) => {
-                warn!(err = ?why, path = %path.display(), "Failed to backup Brave feature state file, continuing anyway");
-            }
-        }
-    }
-
     let prefs_str = fs::read_to_string(&path).unwrap_or_default();
     let mut prefs_parsed =
         serde_json::from_str::<Value>(&prefs_str).unwrap_or_else(|_| Value::Object(Map::new()));
 
     let prefs = prefs_parsed.as_object_mut().wrap_err("failed to parse preferences as an object")?;
+    apply_feature_state(prefs, known);
+
+    let prefs_str = serde_json::to_string(&prefs)?;
+    transaction
+        .write(&path, prefs_str)
+        .wrap_err_with(|| format!("failed to write preferences to {}", path.display()))?;
+
+    debug!("Wrote new chrome preferences");
+    Ok(())
+}
 
+// Without `known` every feature is written, like when exporting
+pub fn apply_feature_state(prefs: &mut Map<String, Value>, known: Option<&KnownFeatures>) {
     // Both features are seperated by commas
     let mut disable_features = prefs
-        .get("enable-features")
+        .get("disable-features")
         .and_then(Value::as_str)
         .unwrap_or_default()
         .split(',')
+        .filter(|f| !f.is_empty())
         .collect::<Vec<_>>();
 
+    // Drop ours that this version doesn't have, older runs may have written them
+    let is_stale = |f: &str| known.is_some_and(|k| !k.contains(f));
     let before = disable_features.len();
+    disable_features.retain(|f| !(DISABLED_FEATURES.contains(f) && is_stale(f)));
+    debug!("Removed {} stale disabled features", before - disable_features.len());
 
+    let before = disable_features.len();
     for feature in DISABLED_FEATURES.iter() {
-        if !disable_features.contains(feature)
+        if !is_stale(feature)
+            && !disable_features.contains(feature)
             && !disable_features.contains(&replace_symbols(feature).as_str())
         {
             disable_features.push(feature);
@@ -61,6 +69,7 @@ pub fn chrome_feature_state(root: &Path) -> color_eyre::Result<()> {
         .and_then(Value::as_str)
         .unwrap_or_default()
         .split(',')
+        .filter(|f| !f.is_empty())
         .collect::<Vec<_>>();
     let before = enabled_features.len();
 
@@ -81,11 +90,4 @@ pub fn chrome_feature_state(root: &Path) -> color_eyre::Result<()> {
     // These are IMMEDIATELY restored anyway
     prefs.insert(s!("force-fieldtrial-params"), json!(""));
     prefs.insert(s!("force-fieldtrials"), json!(""));
-
-    let prefs_str = serde_json::to_string(&prefs)?;
-    fs::write(&path, prefs_str)
-        .wrap_err_with(|| format!("failed to write preferences to {}", path.display()))?;
-
-    debug!("Wrote new chrome preferences");
-    Ok(())
 }
@@ -0,0 +1,228 @@
+use crate::{
+    brave::{Brave, flags::{self, flag_name}, local_state, resources::{DISABLED_FEATURES, LAB_EXPERIMENTS, REMOVE_ENABLED_FEATURES}}, browser::{Browser, installation::Installation}, util::binary
+};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeSet, HashSet}, fs, fs::File, path::{Path, PathBuf}, time::Instant
+};
+use tracing::{debug, info, warn};
+
+// The feature names compiled into an installed Brave, so we only write ones it understands
+pub struct KnownFeatures {
+    pub binary: PathBuf,
+    found: HashSet<String>
+}
+
+impl KnownFeatures {
+    // Looks for our features and `extra` in the installation's binary, if it can be found
+    pub fn scan(installation: &Installation, extra: &[&str]) -> Option<Self> {
+        let Some(binary) = installation.app_folders.iter().find_map(|f| feature_binary(f)) else {
+            debug!("Couldn't find the binary of {installation}, not validating features");
+            return None;
+        };
+
+        let ours = ours().map(|n| n as &str);
+        let names = extra.iter().map(|n| base_name(n)).chain(ours).collect::<BTreeSet<_>>();
+        let start = Instant::now();
+        match scan(&binary, &names) {
+            Ok(found) => {
+                debug!(
+                    binary = %binary.display(),
+                    elapsed = ?start.elapsed(),
+                    "Found {} of {} features",
+                    found.len(),
+                    names.len()
+                );
+                Some(Self { binary, found })
+            }
+            Err(why) => {
+                warn!(err = ?why, "Failed to read {}, not validating features", binary.display());
+                None
+            }
+        }
+    }
+
+    pub fn contains(&self, feature: &str) -> bool {
+        self.found.contains(base_name(feature))
+    }
+
+    // Features and flags in our lists this version of Brave no longer has
+    pub fn stale(&self) -> Vec<&'static str> {
+        let mut stale = ours().filter(|f| !self.contains(f)).collect::<Vec<_>>();
+        stale.sort_unstable();
+        stale.dedup();
+        stale
+    }
+
+    pub fn report_stale(&self, installation: &Installation) {
+        let stale = self.stale();
+        if !stale.is_empty() {
+            info!(
+                "{} of clenzy's features and flags aren't in {installation} anymore, skipped {}",
+                stale.len(),
+                stale.join(", ")
+            );
+        }
+    }
+}
+
+// Our features and brave://flags experiments
+fn ours() -> impl Iterator<Item = &'static str> {
+    let features = DISABLED_FEATURES.iter().chain(REMOVE_ENABLED_FEATURES.iter());
+    features.map(|f| base_name(f)).chain(LAB_EXPERIMENTS.iter().map(|f| flag_name(f)))
+}
+
+// "Feature<Trial.Group:param/value" is just "Feature" as far as the binary is concerned
+pub fn base_name(feature: &str) -> &str {
+    let feature = feature.trim_start_matches('*');
+    let end = [feature.find('<'), feature.find("\\u003C"), feature.find(':')]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(feature.len());
+
+    feature.get(..end).unwrap_or(feature)
+}
+
+// The file the feature names are compiled into, the launcher is only a small wrapper on Windows
+// and macOS
+fn feature_binary(app_folder: &Path) -> Option<PathBuf> {
+    let candidates = if cfg!(target_os = "windows") {
+        // Application/<version>/chrome.dll, newest version first
+        let mut versions = fs::read_dir(app_folder)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name().is_some_and(|n| n.to_string_lossy().starts_with(char::is_numeric))
+            })
+            .collect::<Vec<_>>();
+        versions.sort();
+        versions.into_iter().rev().map(|v| v.join("chrome.dll")).collect()
+    } else if cfg!(target_os = "macos") {
+        // Contents/Frameworks/<name>.framework/Versions/Current/<name>
+        fs::read_dir(app_folder.join("Frameworks"))
+            .ok()?
+            .filter_map(Result::ok)
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().strip_suffix(".framework")?.to_owned();
+                Some(e.path().join("Versions/Current").join(name))
+            })
+            .collect()
+    } else if app_folder.is_file() {
+        // The local package's app folder is the brave-browser launcher script
+        vec![app_folder.with_file_name("brave")]
+    } else {
+        vec![app_folder.join("brave")]
+    };
+
+    candidates.into_iter().find(|c| c.is_file())
+}
+
+// Feature names are stored as plain C strings, so look for each of them with its terminator
+fn scan(binary: &Path, names: &BTreeSet<&str>) -> color_eyre::Result<HashSet<String>> {
+    let names = names.iter().filter(|n| !n.is_empty()).collect::<Vec<_>>();
+    let patterns = names.iter().map(|n| format!("{n}\0")).collect::<Vec<_>>();
+    let (searcher, longest) = binary::searcher(&patterns)?;
+
+    let mut found = HashSet::new();
+    binary::find_patterns(&mut File::open(binary)?, &searcher, longest, &mut found)?;
+    Ok(found.into_iter().filter_map(|i| names.get(i)).map(|n| (**n).to_owned()).collect())
+}
+
+// A feature forced on or off
+struct Forced {
+    name: String,
+    enabled: bool
+}
+
+fn forced_features(feature_state: &Map<String, Value>) -> Vec<Forced> {
+    let list = |key: &str, enabled: bool| {
+        feature_state
+            .get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .split(',')
+            .filter(|f| !f.is_empty())
+            .map(|f| Forced { name: f.replace("\\u003C", "<"), enabled })
+            .collect::<Vec<_>>()
+    };
+
+    let mut forced = list("enable-features", true);
+    forced.extend(list("disable-features", false));
+    forced
+}
+
+// Shows every feature and flag forced on or off, and whether the installed Brave still has it
+pub fn features_command(installations: &[&Installation]) {
+    let ours = ours().collect::<HashSet<_>>();
+    let installations = installations.iter().filter(|i| i.browser_name == Brave::name());
+
+    let mut shown = 0;
+    for installation in installations {
+        for data_folder in &installation.data_folders {
+            let feature_state = fs::read_to_string(data_folder.join("ChromeFeatureState"))
+                .ok()
+                .and_then(|s| serde_json::from_str::<Map<String, Value>>(&s).ok())
+                .unwrap_or_default();
+            let forced = forced_features(&feature_state);
+            let flags = local_state::get_local_state(data_folder)
+                .map(|l| flags::current(&l))
+                .unwrap_or_default();
+
+            let extra = forced
+                .iter()
+                .map(|f| f.name.as_str())
+                .chain(flags.iter().map(|f| flag_name(f)))
+                .collect::<Vec<_>>();
+            let known = KnownFeatures::scan(installation, &extra);
+            let status = |name: &str| match &known {
+                Some(known) if known.contains(name) => "",
+                Some(_) => "  (unknown to this version)",
+                None => "  (couldn't check)"
+            };
+
+            info!("{installation} in {}", data_folder.display());
+            if let Some(known) = &known {
+                info!("  Checked against {}", known.binary.display());
+            } else {
+                warn!("  Couldn't find Brave's binary, so features can't be checked");
+            }
+
+            info!("  ChromeFeatureState:");
+            if forced.is_empty() {
+                info!("    Nothing forced on or off");
+            }
+            for feature in &forced {
+                let state = if feature.enabled { "enabled " } else { "disabled" };
+                let by = if ours.contains(base_name(&feature.name)) { " [clenzy]" } else { "" };
+                info!("    {state}  {}{by}{}", feature.name, status(&feature.name));
+            }
+
+            info!("  Local State (brave://flags):");
+            if flags.is_empty() {
+                info!("    No flags changed");
+            }
+            for flag in &flags {
+                info!("    set       {flag}{}", status(flag_name(flag)));
+            }
+
+            if let Some(known) = &known {
+                let stale = known.stale();
+                if !stale.is_empty() {
+                    info!(
+                        "  clenzy's lists have {} features and flags this version doesn't: {}",
+                        stale.len(),
+                        stale.join(", ")
+                    );
+                }
+            }
+
+            shown += 1;
+        }
+    }
+
+    if shown == 0 {
+        info!("No Brave installations found");
+    }
+}
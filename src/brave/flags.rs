@@ -0,0 +1,242 @@
+use crate::{
+    brave::{Brave, backup, features::KnownFeatures, local_state, resources::LAB_EXPERIMENTS}, browser::{Browser, installation::Installation}, util::{args, lock::{LockKind, ensure_unlocked}, logging::success, transaction::Transaction}
+};
+use color_eyre::eyre::{bail, ContextCompat, WrapErr};
+use serde_json::{Map, Value, json};
+use std::{fmt::Display, path::Path, str::FromStr};
+use tracing::{debug, info, warn};
+
+// A brave://flags experiment and the option picked for it, no option leaves it at its default
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Flag {
+    pub name: String,
+    pub option: Option<u32>
+}
+
+impl FromStr for Flag {
+    type Err = color_eyre::Report;
+
+    // name@2, or name=enabled, name=disabled and name=default
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, option) = if let Some((name, option)) = s.split_once('@') {
+            let option = option
+                .parse::<u32>()
+                .wrap_err_with(|| format!("Invalid option {option} for flag {name}"))?;
+            (name, Some(option))
+        } else if let Some((name, state)) = s.split_once('=') {
+            let option = match state {
+                "enabled" => Some(1),
+                "disabled" => Some(2),
+                "default" => None,
+                _ => bail!("Flag {name} can only be enabled, disabled or default, not {state}")
+            };
+            (name, option)
+        } else {
+            bail!("Flag {s} needs an option, like {s}@1 or {s}=disabled");
+        };
+
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            bail!("Invalid flag name {name:?}");
+        }
+
+        Ok(Self { name: name.to_owned(), option })
+    }
+}
+
+impl Display for Flag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.option {
+            Some(option) => write!(f, "{}@{option}", self.name),
+            None => write!(f, "{}=default", self.name)
+        }
+    }
+}
+
+pub fn flag_name(entry: &str) -> &str {
+    entry.split_once('@').map_or(entry, |(name, _)| name)
+}
+
+// Ours unless --no-brave-flags, with every --brave-flag replacing ours of the same name
+pub fn wanted() -> color_eyre::Result<Vec<Flag>> {
+    let mut flags = if args().brave_flags_default {
+        LAB_EXPERIMENTS.iter().map(|f| f.parse()).collect::<color_eyre::Result<Vec<Flag>>>()?
+    } else {
+        Vec::new()
+    };
+
+    for flag in &args().brave_flags {
+        let flag = flag.parse::<Flag>()?;
+        flags.retain(|f| f.name != flag.name);
+        flags.push(flag);
+    }
+
+    Ok(flags)
+}
+
+// What brave://flags has set, as name@option
+pub fn current(local_state: &Map<String, Value>) -> Vec<String> {
+    local_state
+        .get("browser")
+        .and_then(|b| b.get("enabled_labs_experiments"))
+        .and_then(Value::as_array)
+        .map(|flags| flags.iter().filter_map(Value::as_str).map(String::from).collect())
+        .unwrap_or_default()
+}
+
+// Sets each flag in Local State, and returns the ones it skipped because this version of Brave
+// doesn't have them
+pub fn apply<'a>(
+    local_state: &mut Map<String, Value>,
+    flags: &'a [Flag],
+    known: Option<&KnownFeatures>
+) -> color_eyre::Result<Vec<&'a Flag>> {
+    let experiments = local_state
+        .get_mut("browser")
+        .and_then(Value::as_object_mut)
+        .wrap_err("Failed to get browser object")?
+        .entry("enabled_labs_experiments")
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+        .wrap_err("enabled_labs_experiments isn't a list")?;
+
+    let mut skipped = Vec::new();
+    for flag in flags {
+        if known.is_some_and(|k| !k.contains(&flag.name)) {
+            skipped.push(flag);
+            continue;
+        }
+
+        experiments.retain(|e| e.as_str().is_none_or(|e| flag_name(e) != flag.name));
+        if let Some(option) = flag.option {
+            experiments.push(json!(format!("{}@{option}", flag.name)));
+        }
+    }
+
+    debug!("Set {} flags, {} in total", flags.len() - skipped.len(), experiments.len());
+    Ok(skipped)
+}
+
+// Only flags that were asked for are worth a warning, clenzy's own ones come and go with Brave
+pub fn report_skipped(skipped: &[&Flag], explicit: &[String]) {
+    for flag in skipped {
+        if explicit.contains(&flag.name) {
+            warn!("This version of Brave doesn't have the flag {}, it wasn't set", flag.name);
+        } else {
+            debug!("Skipping flag {} that this version of Brave doesn't have", flag.name);
+        }
+    }
+}
+
+// Names of the flags given with --brave-flag
+pub fn explicit() -> Vec<String> {
+    args().brave_flags.iter().filter_map(|f| f.parse::<Flag>().ok()).map(|f| f.name).collect()
+}
+
+pub fn list_command(installations: &[&Installation]) {
+    let wanted = wanted().unwrap_or_default();
+
+    let mut shown = 0;
+    for installation in brave_installations(installations) {
+        for data_folder in &installation.data_folders {
+            let local_state = match local_state::get_local_state(data_folder) {
+                Ok(local_state) => local_state,
+                Err(why) => {
+                    warn!(err = ?why, "Failed to read Local State in {}", data_folder.display());
+                    continue;
+                }
+            };
+
+            let flags = current(&local_state);
+            let names = flags.iter().map(|f| flag_name(f)).collect::<Vec<_>>();
+            let known = KnownFeatures::scan(installation, &names);
+
+            info!("{installation} in {}", data_folder.display());
+            if flags.is_empty() {
+                info!("  No flags changed");
+            }
+            for flag in &flags {
+                let ours = wanted.iter().any(|w| w.to_string() == *flag);
+                let by = if ours { " [clenzy]" } else { "" };
+                let unknown = known.as_ref().is_some_and(|k| !k.contains(flag_name(flag)));
+                let status = if unknown { "  (unknown to this version)" } else { "" };
+                info!("  {flag}{by}{status}");
+            }
+
+            shown += 1;
+        }
+    }
+
+    if shown == 0 {
+        info!("No Brave installations found");
+    }
+}
+
+// Sets flags in every Brave data folder, without touching anything else
+pub fn set_command(installations: &[&Installation], flags: &[String]) -> color_eyre::Result<()> {
+    let flags = flags.iter().map(|f| f.parse()).collect::<color_eyre::Result<Vec<Flag>>>()?;
+    if flags.is_empty() {
+        bail!("No flags to set");
+    }
+
+    let mut set = 0;
+    let names = flags.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+    for installation in brave_installations(installations) {
+        let known = KnownFeatures::scan(installation, &names);
+        for data_folder in &installation.data_folders {
+            let applied = match set_flags(installation, data_folder, &flags, known.as_ref()) {
+                Ok(applied) => applied,
+                Err(why) => {
+                    warn!(err = ?why, "Failed to set flags in {}", data_folder.display());
+                    continue;
+                }
+            };
+
+            success(&format!("Set {applied} flags for {installation}"));
+            set += 1;
+        }
+    }
+
+    if set == 0 {
+        bail!("No Brave data folders were changed");
+    }
+
+    Ok(())
+}
+
+fn set_flags(
+    installation: &Installation,
+    data_folder: &Path,
+    flags: &[Flag],
+    known: Option<&KnownFeatures>
+) -> color_eyre::Result<usize> {
+    // Brave writes Local State back when it exits
+    if !ensure_unlocked(LockKind::Chromium, data_folder, &data_folder.display()) {
+        bail!("Data folder is in use");
+    }
+
+    let mut local_state = local_state::get_local_state(data_folder)?;
+    let skipped = apply(&mut local_state, flags, known)?;
+    let names = flags.iter().map(|f| f.name.clone()).collect::<Vec<_>>();
+    report_skipped(&skipped, &names);
+    if skipped.len() == flags.len() {
+        bail!("This version of Brave has none of the flags");
+    }
+
+    if args().backup {
+        backup::backup_data_folder(installation, data_folder)
+            .wrap_err("Failed to backup data folder")?;
+    }
+
+    let mut transaction = Transaction::new();
+    transaction
+        .write(&data_folder.join("Local State"), serde_json::to_string(&local_state)?)
+        .wrap_err("Failed to write Local State")?;
+    transaction.commit()?;
+    Ok(flags.len() - skipped.len())
+}
+
+fn brave_installations<'a>(
+    installations: &'a [&Installation]
+) -> impl Iterator<Item = &'a Installation> {
+    installations.iter().copied().filter(|i| i.browser_name == Brave::name())
+}
@@ -0,0 +1,263 @@
+use crate::{
+    brave::{Brave, preferences::apply_preferences}, browser::{
+        Browser, installation::{Installation, InstalledVia}
+    }, util::{is_permission_denied, logging::success, transaction::write_atomic}
+};
+#[cfg(target_os = "linux")]
+use crate::util::elevate::{self, PrivilegedWrite};
+use color_eyre::eyre::{WrapErr, bail};
+use serde_json::{Map, Value};
+use std::{
+    fmt::Display, fs, path::{Path, PathBuf}
+};
+use tracing::{debug, info, warn};
+
+// Chromium still falls back to master_preferences, but only reads it if this doesn't exist
+const FILE_NAME: &str = "initial_preferences";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Current,
+    Outdated,
+    Missing,
+    // Snap and Flatpak apps live on read-only mounts
+    ReadOnly
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Current => write!(f, "present and current"),
+            Self::Outdated => write!(f, "present but outdated"),
+            Self::Missing => write!(f, "missing"),
+            Self::ReadOnly => write!(f, "not installable, the app folder is read-only")
+        }
+    }
+}
+
+// Chromium reads initial_preferences from the directory of its binary. On macOS only Google Chrome
+// branded builds look for it at all.
+fn target_dirs(installation: &Installation) -> Vec<PathBuf> {
+    if cfg!(target_os = "macos") {
+        return Vec::new();
+    }
+
+    let mut dirs = installation
+        .app_folders
+        .iter()
+        // On Linux the app folder is the `brave-browser` launcher itself
+        .filter_map(|f| {
+            if f.is_file() { f.parent().map(Path::to_path_buf) } else { Some(f.clone()) }
+        })
+        .collect::<Vec<_>>();
+    dirs.dedup();
+    dirs
+}
+
+pub fn generate(existing: Option<&Map<String, Value>>) -> color_eyre::Result<Map<String, Value>> {
+    let mut prefs = Map::new();
+    apply_preferences(&mut prefs)?;
+
+    // Keep whatever else an administrator already put in there
+    let mut merged = existing.cloned().unwrap_or_default();
+    merge(&mut merged, prefs);
+    Ok(merged)
+}
+
+fn merge(into: &mut Map<String, Value>, from: Map<String, Value>) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(Value::Object(existing)), Value::Object(value)) => merge(existing, value),
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+fn read_existing(path: &Path) -> color_eyre::Result<Option<Map<String, Value>>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(why) => return Err(why).wrap_err_with(|| format!("Failed to read {}", path.display()))
+    };
+
+    let Value::Object(existing) = serde_json::from_str::<Value>(&contents)
+        .wrap_err_with(|| format!("Failed to parse {}", path.display()))?
+    else {
+        bail!("{} isn't a JSON object", path.display());
+    };
+
+    Ok(Some(existing))
+}
+
+fn status(installation: &Installation, dir: &Path) -> color_eyre::Result<Status> {
+    let Some(existing) = read_existing(&dir.join(FILE_NAME))? else {
+        return Ok(if installation.installed_via == InstalledVia::Local {
+            Status::Missing
+        } else {
+            Status::ReadOnly
+        });
+    };
+
+    Ok(if generate(Some(&existing))? == existing { Status::Current } else { Status::Outdated })
+}
+
+// Logs the state of initial_preferences for every app folder, returns whether all are current
+pub fn check(installations: &[&Installation]) -> bool {
+    let mut all_current = true;
+    let installations =
+        installations.iter().filter(|i| i.browser_name == Brave::name() && i.is_valid());
+    for installation in installations {
+        let dirs = target_dirs(installation);
+        if dirs.is_empty() {
+            info!("{installation}: initial_preferences isn't supported here");
+            continue;
+        }
+
+        for dir in dirs {
+            match status(installation, &dir) {
+                Ok(Status::Current) => success(&format!("{installation}: {}", dir.display())),
+                Ok(status) => {
+                    all_current = false;
+                    warn!("{installation}: initial_preferences in {} is {status}", dir.display());
+                }
+                Err(why) => {
+                    all_current = false;
+                    warn!(err = ?why, "{installation}: failed to check {}", dir.display());
+                }
+            }
+        }
+    }
+
+    all_current
+}
+
+// Makes new profiles start out with the same prefs existing ones get
+pub fn install(installation: &Installation) -> color_eyre::Result<()> {
+    if installation.installed_via != InstalledVia::Local {
+        warn!("{installation} is installed read-only, skipping initial_preferences");
+        return Ok(());
+    }
+
+    let dirs = target_dirs(installation);
+    if dirs.is_empty() {
+        debug!("No app folders to install initial_preferences into for {installation}");
+        return Ok(());
+    }
+
+    let mut denied = Vec::new();
+    for dir in dirs {
+        match write(installation, &dir) {
+            Ok(()) => {}
+            Err(why) if is_permission_denied(&why) => {
+                debug!(err = ?why, path = %dir.display(), "App folder isn't writable");
+                denied.push(dir);
+            }
+            Err(why) => return Err(why)
+        }
+    }
+
+    if denied.is_empty() {
+        return Ok(());
+    }
+
+    install_with_elevation(installation, &denied)
+}
+
+#[cfg(target_os = "linux")]
+fn install_with_elevation(
+    installation: &Installation,
+    denied: &[PathBuf]
+) -> color_eyre::Result<()> {
+    let mut files = Vec::with_capacity(denied.len());
+    for dir in denied {
+        let path = dir.join(FILE_NAME);
+        let prefs = generate(read_existing(&path)?.as_ref())?;
+        files.push(PrivilegedWrite::new(path, serde_json::to_string_pretty(&prefs)?));
+    }
+
+    if !elevate::write_elevated("Installing Brave's initial_preferences", &files)? {
+        return Ok(());
+    }
+
+    for dir in denied {
+        if status(installation, dir)? != Status::Current {
+            bail!("initial_preferences in {} wasn't updated", dir.display());
+        }
+    }
+
+    success(&format!("Installed initial_preferences for {installation}"));
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn install_with_elevation(
+    installation: &Installation,
+    denied: &[PathBuf]
+) -> color_eyre::Result<()> {
+    if !crate::util::should_elevate("Installing Brave's initial_preferences") {
+        return Ok(());
+    }
+
+    crate::util::elevate_and_run("--brave-initial-preferences-elevated")?;
+
+    for dir in denied {
+        if status(installation, dir)? != Status::Current {
+            bail!("initial_preferences in {} wasn't updated", dir.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn install_with_elevation(
+    _installation: &Installation,
+    denied: &[PathBuf]
+) -> color_eyre::Result<()> {
+    for dir in denied {
+        warn!(
+            "{} isn't writable, run clenzy with sudo to install initial_preferences",
+            dir.display()
+        );
+    }
+
+    Ok(())
+}
+
+// Runs as the elevated child, nothing here may prompt
+pub fn install_elevated(installations: &[&Installation]) -> color_eyre::Result<()> {
+    for installation in installations.iter().filter(|i| i.browser_name == Brave::name()) {
+        if installation.installed_via != InstalledVia::Local {
+            continue;
+        }
+
+        for dir in target_dirs(installation) {
+            write(installation, &dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write(installation: &Installation, dir: &Path) -> color_eyre::Result<()> {
+    let path = dir.join(FILE_NAME);
+    let existing = read_existing(&path)?;
+    let prefs = generate(existing.as_ref())?;
+
+    if existing.as_ref() == Some(&prefs) {
+        debug!(path = %path.display(), "initial_preferences is already current");
+        return Ok(());
+    }
+
+    write_atomic(&path, serde_json::to_string_pretty(&prefs)?)
+        .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+
+    if status(installation, dir)? != Status::Current {
+        bail!("{} doesn't match what was written", path.display());
+    }
+
+    success(&format!("Installed initial_preferences for {installation}"));
+    Ok(())
+}
@@ -1,9 +1,8 @@
-use crate::{brave::resources::REMOVE_ENABLED_LAB_FEATURES, s, util::get_or_insert_obj};
+use crate::{brave::{features::KnownFeatures, flags}, s, util::get_or_insert_obj};
 use color_eyre::eyre::{bail, ContextCompat, WrapErr};
 use serde_json::{json, Map, Value};
 use std::{fs, path::Path};
-use tracing::debug;
-use crate::util::args;
+use crate::util::{args, transaction::Transaction};
 
 pub fn get_local_state(root: &Path) -> color_eyre::Result<Map<String, Value>> {
     let local_state_path = root.join("Local State");
@@ -21,8 +20,15 @@ pub fn get_local_state(root: &Path) -> color_eyre::Result<Map<String, Value>> {
 
 pub fn update_local_state(
     mut local_state: Map<String, Value>,
-    root: &Path
+    root: &Path,
+    known: Option<&KnownFeatures>,
+    transaction: &mut Transaction
 ) -> color_eyre::Result<()> {
+    // Flags show up in brave://flags, so users can see and undo them there
+    let flags = flags::wanted()?;
+    let skipped = flags::apply(&mut local_state, &flags, known)?;
+    flags::report_skipped(&skipped, &flags::explicit());
+
     let brave = local_state
         .get_mut("brave")
         .and_then(Value::as_object_mut)
@@ -68,23 +74,9 @@ pub fn update_local_state(
         .and_then(Value::as_object_mut)
         .wrap_err("Failed to get browser object")?;
 
-    if let Some(enabled_lab_features) =
-        browser.get_mut("enabled_lab_features").and_then(Value::as_array_mut)
-    {
-        let before = enabled_lab_features.len();
-        enabled_lab_features.retain(|feature| {
-            let Some(s) = feature.as_str() else {
-                return true;
-            };
-
-            // Return true to keep if the feature is not in the blacklist
-            !REMOVE_ENABLED_LAB_FEATURES.contains(&s)
-        });
-        debug!(before = %before, after = %enabled_lab_features.len(), "Removed {} enabled lab features", before - enabled_lab_features.len());
-    }
-
     browser.insert(s!("default_browser_infobar_declined_count"), json!(9999));
 
-    fs::write(root.join("Local State"), serde_json::to_string(&local_state)?)
+    transaction
+        .write(&root.join("Local State"), serde_json::to_string(&local_state)?)
         .wrap_err("Failed to write Local State")
 }
@@ -1,4 +1,8 @@
+mod backup;
 mod chrome_feature_state;
+mod features;
+mod flags;
+pub mod initial_preferences;
 mod local_state;
 mod installations;
 mod preferences;
@@ -6,20 +10,27 @@ mod profiles;
 mod resources;
 mod policies;
 
-pub use policies::create_policies;
+pub use chrome_feature_state::apply_feature_state;
+pub use features::features_command;
+pub use flags::{list_command as flags_list_command, set_command as flags_set_command};
+pub use policies::{create_policies, installed_policies, linux_policy_file, validated_policies};
 #[cfg(target_os = "windows")]
 pub use policies::create_policies_windows;
 #[cfg(target_os = "linux")]
-pub use policies::create_policies_linux;
+pub use policies::{create_policies_linux, verify_linux_policies};
 
 use std::path::Path;
+use color_eyre::eyre::WrapErr;
 use crate::browser::profile::BrowserProfile;
 use tracing::{debug, debug_span, warn};
 use crate::browser::installation::Installation;
 
+use features::KnownFeatures;
 use installations::installations;
 use crate::browser::Browser;
 use crate::util::args;
+use crate::util::lock::{LockKind, ensure_unlocked};
+use crate::util::transaction::Transaction;
 
 pub struct Brave;
 
@@ -32,18 +43,29 @@ impl Browser for Brave {
         installations()
     }
 
-    fn debloat(installation: &Installation) -> color_eyre::Result<()> {
+    fn debloat(
+        installation: &Installation,
+        transaction: &mut Transaction
+    ) -> color_eyre::Result<()> {
+        let flags = flags::wanted()?;
+        let names = flags.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+        let known = KnownFeatures::scan(installation, &names);
+        if let Some(known) = &known {
+            known.report_stale(installation);
+        }
+
         for data_folder in &installation.data_folders {
-            if let Err(why) = debloat_data_folder(data_folder) {
-                warn!(err = ?why, "Failed to debloat data folder: {}", data_folder.display());
-            } else {
-        // for data_folder in &installation.data_folders {
-        //     if let Err(why) = debloat_data_folder(data_folder) {
-        //         warn!(err = ?why, "Failed to debloat data folder: {}", data_folder.display());
-        //     } else {
-        //         debug!(data_folder = %data_folder.display(), "Successfully debloated data folder");
-        //     }
-        // }
+            debloat_data_folder(installation, data_folder, known.as_ref(), transaction)
+                .wrap_err_with(|| {
+                    format!("Failed to debloat data folder: {}", data_folder.display())
+                })?;
+            debug!(data_folder = %data_folder.display(), "Successfully debloated data folder");
+        }
+
+        if args().initial_preferences {
+            initial_preferences::install(installation)
+                .wrap_err("Failed to install initial_preferences")?;
+        }
 
         if args().policies {
             if let Err(why) = create_policies(installation) {
@@ -57,7 +79,23 @@ impl Browser for Brave {
     }
 }
 
-fn debloat_data_folder(data_folder: &Path) -> color_eyre::Result<()> {
+fn debloat_data_folder(
+    installation: &Installation,
+    data_folder: &Path,
+    known: Option<&KnownFeatures>,
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
+    // Every profile shares the lock of the data folder, and Local State is rewritten on exit
+    // Skipping it leaves the other data folders and the policies to be debloated
+    if !ensure_unlocked(LockKind::Chromium, data_folder, &data_folder.display()) {
+        return Ok(());
+    }
+
+    if args().backup {
+        backup::backup_data_folder(installation, data_folder)
+            .wrap_err("Failed to backup data folder")?;
+    }
+
     let local_state = local_state::get_local_state(data_folder)?;
 
     let profiles = match profiles::try_to_get_profiles(data_folder, &local_state) {
@@ -71,24 +109,26 @@ fn debloat_data_folder(data_folder: &Path) -> color_eyre::Result<()> {
         }
     };
 
-    match local_state::update_local_state(local_state, data_folder) {
-        Ok(()) => debug!("Updated brave's local state"),
-        Err(why) => warn!(err = ?why, "Failed to brave's update local state")
-    }
+    local_state::update_local_state(local_state, data_folder, known, transaction)
+        .wrap_err("Failed to update brave's local state")?;
+    debug!("Updated brave's local state");
 
-    match chrome_feature_state::chrome_feature_state(data_folder) {
-        Ok(()) => debug!("Updated brave's ChromeFeatureState"),
-        Err(why) => warn!(err = ?why, "Failed to update brave's ChromeFeatureState")
-    }
+    chrome_feature_state::chrome_feature_state(data_folder, known, transaction)
+        .wrap_err("Failed to update brave's ChromeFeatureState")?;
+    debug!("Updated brave's ChromeFeatureState");
 
     for profile in profiles {
         let span = debug_span!("Debloating brave profile", profile = %profile.name);
         let _enter = span.enter();
 
-        match preferences::preferences(&profile.path) {
-            Ok(()) => debug!("Finished debloating brave profile {profile}"),
-            Err(why) => warn!(err = ?why, "Failed to debloat preferences for profile {profile}")
+        if args().backup {
+            backup::backup_profile(installation, &profile)
+                .wrap_err_with(|| format!("Failed to backup profile {profile}"))?;
         }
+
+        preferences::preferences(&profile.path, transaction)
+            .wrap_err_with(|| format!("Failed to debloat preferences for profile {profile}"))?;
+        debug!("Finished debloating brave profile {profile}");
     }
 
     Ok(())
@@ -1,15 +1,45 @@
 use crate::{
-    Args, brave, brave::Brave, browser::installation::{Installation, Variant}, util::{UnwrapOrExit, args, logging::success}
+    browser::installation::Installation, util::{args, backups::Slot, content_settings, hardening, policy_schema}
 };
-use color_eyre::eyre::{Context, ContextCompat};
-use std::{fs, path::Path, process::Stdio, sync::LazyLock};
+#[cfg(not(target_os = "linux"))]
+use crate::util::logging::success;
+use crate::{Enforcement, browser::installation::InstalledVia};
+#[cfg(target_os = "linux")]
+use crate::{
+    brave::Brave, browser::Browser, util::elevate::{self, PrivilegedWrite}
+};
+#[cfg(target_os = "linux")]
+use color_eyre::eyre::bail;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(target_os = "macos")]
+use crate::browser::installation::Variant;
+use color_eyre::eyre::Context;
+#[cfg(target_os = "macos")]
+use color_eyre::eyre::ContextCompat;
+use std::{fs, sync::LazyLock};
 use tracing::warn;
 
 static POLICIES: LazyLock<serde_json::Map<String, serde_json::Value>> = LazyLock::new(|| {
-    serde_json::from_str(include_str!("../../snippets/brave/policies.json"))
-        .expect("to parse policies json file")
+    let mut policies: serde_json::Map<_, _> =
+        serde_json::from_str(include_str!("../../snippets/brave/policies.json"))
+            .expect("to parse policies json file");
+    policies.extend(content_settings::chromium_policies());
+    policies.extend(hardening::chromium_policies());
+    policy_schema::coerce_chromium(&mut policies);
+    policies
 });
 
+// Where the policies were read from, and what was read
+pub type PolicySource = (String, color_eyre::Result<serde_json::Map<String, serde_json::Value>>);
+
+pub fn validated_policies()
+-> color_eyre::Result<&'static serde_json::Map<String, serde_json::Value>> {
+    policy_schema::ensure_valid("Brave policies", &policy_schema::validate_chromium(&POLICIES))?;
+    Ok(&POLICIES)
+}
+
 pub fn create_policies(installation: &Installation) -> color_eyre::Result<()> {
     create(installation, args().backup)
 }
@@ -26,17 +56,22 @@ pub fn create_policies_windows(
     use windows_registry::*;
 
     // FIXME for beta/nightly?
-    let mut policies_key = match LOCAL_MACHINE
-        // Creates or opens
-        .create("Software\\Policies\\BraveSoftware\\Brave")
-    {
+    let path = match args().enforcement {
+        crate::Enforcement::Recommended => "Software\\Policies\\BraveSoftware\\Brave\\Recommended",
+        crate::Enforcement::Managed | crate::Enforcement::Locked => {
+            "Software\\Policies\\BraveSoftware\\Brave"
+        }
+    };
+
+    // Creates or opens
+    let mut policies_key = match LOCAL_MACHINE.create(path) {
         Ok(key) => key,
         Err(why) => {
             if short_circuit {
                 return Err(why).wrap_err("Permission error even with elevated permissions");
             }
 
-            if !crate::util::should_elevate() {
+            if !crate::util::should_elevate("Policy creation") {
                 return Ok(());
             }
 
@@ -44,13 +79,9 @@ pub fn create_policies_windows(
         }
     };
 
-    fn stringify(v: Vec<(String, Value)>) -> String {
-        let mut backup = String::from(
-            r#"Windows Registry Editor Version 5.00
-
-[HKEY_LOCAL_MACHINE\SOFTWARE\Policies\BraveSoftware\Brave]
-"#
-        );
+    fn stringify(path: &str, v: Vec<(String, Value)>) -> String {
+        let mut backup =
+            format!("Windows Registry Editor Version 5.00\n\n[HKEY_LOCAL_MACHINE\\{path}]\n");
 
         for (key, value) in v {
             if let Ok(n) = TryInto::<u32>::try_into(value) {
@@ -64,18 +95,19 @@ pub fn create_policies_windows(
     let original = if should_backup {
         let v =
             policies_key.values().map(|v| v.collect::<Vec<(String, Value)>>()).unwrap_or_default();
-        Some(stringify(v))
+        Some(stringify(path, v))
     } else {
         None
     };
 
     let mut inserted_new_lines = false;
-    for (key, value) in POLICIES.iter() {
+    for (key, value) in validated_policies()? {
         if policies_key.get_value(key).is_ok() {
             continue;
         };
 
-        if let Some(n) = value.as_u64() {
+        // The registry stores booleans as DWORDs
+        if let Some(n) = value.as_u64().or_else(|| value.as_bool().map(u64::from)) {
             inserted_new_lines = true;
             policies_key.set_u32(key, n as u32).wrap_err_with(|| {
                 format!("failed to set key {key} with value {n} in Brave policies")
@@ -91,18 +123,10 @@ pub fn create_policies_windows(
         return Ok(());
     };
 
-    let backup_path = installation.data_folders.first().map(|f| {
-        f.join(format!("policies-backup-{}.reg", chrono::Utc::now().format("%Y%m%d%H%M")))
-    });
-
-    if let Some(p) = backup_path {
-        if let Err(why) = fs::write(&p, stringified.as_bytes()) {
-            warn!(err = ?why, "Failed to write backup file: {}", p.display());
-        } else {
-            success(&format!("Backed up policies for {installation}"));
-        }
-    } else {
-        warn!("Failed to find backup path for Brave policies, continuing anyway");
+    let slot = Slot::policies(installation);
+    match slot.save("policies.reg", stringified, None) {
+        Ok(_) => success(&format!("Backed up policies for {installation}")),
+        Err(why) => warn!(err = ?why, "Failed to backup Brave policies, continuing anyway")
     }
 
     Ok(())
@@ -130,22 +154,14 @@ fn create(installation: &Installation, should_backup: bool) -> color_eyre::Resul
         .and_then(plist::Value::into_dictionary)
         .unwrap_or_else(plist::Dictionary::new);
 
-    fn backup(i: &Installation, name: &str, p: &[u8]) -> Option<()> {
-        let root = i.data_folders.first()?;
-        let backup_path =
-            root.join(format!("{name}-{}.plist", chrono::Utc::now().format("%Y%m%d%H%M")));
-
-        fs::write(&backup_path, p).ok()
-    }
-
     let mut new_plist = plist.clone();
-    for (key, value) in POLICIES.iter() {
+    for (key, value) in validated_policies()? {
         if plist.get(key).is_some() {
             continue;
         }
 
         let val = match value {
-            // Only 0's and 1's for now
+            serde_json::Value::Bool(b) => plist::Value::Boolean(*b),
             serde_json::Value::Number(n) => plist::Value::Integer(n.as_i64().unwrap().into()),
             serde_json::Value::String(s) => plist::Value::String(s.clone()),
             _ => continue
@@ -158,65 +174,251 @@ fn create(installation: &Installation, should_backup: bool) -> color_eyre::Resul
         && should_backup
         && plist != new_plist
     {
-        if backup(installation, &file_name, d).is_some() {
-            success(&format!("Backed up existing Brave policy file for {installation}"));
-        } else {
-            warn!("Failed to backup existing Brave policy file for {installation}");
+        let slot = Slot::policies(installation);
+        match slot.save(&format!("{file_name}.plist"), d, Some(&plist_path)) {
+            Ok(_) => success(&format!("Backed up existing Brave policy file for {installation}")),
+            Err(why) => {
+                warn!(err = ?why, "Failed to backup existing Brave policy file for {installation}");
+            }
         }
     }
 
-    plist::to_file_binary(&plist_path, &new_plist).wrap_err("Failed to save Brave plist file")
+    let mut plist_bytes = Vec::new();
+    plist::to_writer_binary(&mut plist_bytes, &new_plist)
+        .wrap_err("Failed to serialize Brave plist")?;
+    crate::util::transaction::write_atomic(&plist_path, plist_bytes)
+        .wrap_err("Failed to save Brave plist file")
+}
+
+// Where each packaging of Brave reads system policies from, the snap can't see any of the host's
+fn linux_base(installed_via: InstalledVia) -> Option<PathBuf> {
+    match installed_via {
+        InstalledVia::Local => Some(PathBuf::from("/etc/brave/policies")),
+        InstalledVia::Snap => None,
+        // Mounted into the sandbox through the com.brave.Browser.Policy extension point
+        InstalledVia::Flatpak => Some(PathBuf::from(format!(
+            "/var/lib/flatpak/extension/com.brave.Browser.Policy.system-policies/{}/1/policies",
+            std::env::consts::ARCH
+        )))
+    }
 }
 
 #[cfg(target_os = "linux")]
-#[allow(clippy::items_after_statements)]
 // json
-pub fn create_policies_linux(should_backup: bool, short_circuit: bool) -> color_eyre::Result<()> {
-    let root = std::path::Path::new("/etc/brave/policies/managed/");
-    if let Err(why) = fs::create_dir_all(root) {
-        tracing::debug!(path = %root.display(), err = ?why, "Create dir all returned error for Brave policies");
-
-        if short_circuit {
-            return Err(why).wrap_err("Permission error even with elevated permissions");
+pub fn create_policies_linux(
+    installations: &[&Installation],
+    should_backup: bool
+) -> color_eyre::Result<()> {
+    let mut bases = Vec::new();
+    for installation in installations.iter().filter(|i| i.browser_name == Brave::name()) {
+        if let Some(base) = linux_base(installation.installed_via) {
+            bases.push((base, installation.installed_via));
+        } else {
+            warn!("{installation} doesn't read policies from the host, skipping");
         }
+    }
+    bases.sort_by(|a, b| a.0.cmp(&b.0));
+    bases.dedup_by(|a, b| a.0 == b.0);
 
-        if !crate::util::should_elevate() {
-            return Ok(());
-        }
+    // Switching levels shouldn't leave our keys behind at the old one
+    let other = match args().enforcement {
+        Enforcement::Recommended => Enforcement::Managed,
+        Enforcement::Managed | Enforcement::Locked => Enforcement::Recommended
+    };
+
+    let mut files = Vec::new();
+    for (base, installed_via) in &bases {
+        let slot = should_backup.then(|| Slot::system(Brave::name(), *installed_via, None));
+        files.extend(linux_policies(base, slot.as_ref())?);
 
-        return crate::util::elevate_and_run("--linux-brave-policies");
+        match released_linux_policies(base, other) {
+            Ok(released) => files.extend(released),
+            Err(why) => {
+                warn!(err = ?why, "Failed to remove clenzy's Brave policies from the other level");
+            }
+        }
     }
 
-    let policies_path = root.join("custom-policy.json");
-    let policies_data = fs::read(&policies_path).ok();
-    let existing_policies = policies_data
-        .as_ref()
-        .and_then(|c| serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&c).ok())
-        .unwrap_or(serde_json::Map::new());
+    elevate::write_or_elevate("Policy creation", &files)
+}
 
-    let mut new_policies = existing_policies.clone();
-    for (key, value) in POLICIES.iter() {
-        if existing_policies.get(key).is_none() {
-            new_policies.insert(key.clone(), value.clone());
-        }
+// Merges ours into the policies already at the current level, returns the files to write, if any
+#[cfg(target_os = "linux")]
+fn linux_policies(base: &Path, backup: Option<&Slot>) -> color_eyre::Result<Vec<PrivilegedWrite>> {
+    use crate::util::policy_merge;
+
+    // Brave reads every file in managed/, so anything that isn't a policy lives one level up
+    let (dir, owned_file) = linux_level(args().enforcement);
+    let policies_path = base.join(dir).join("custom-policy.json");
+    let owned_path = base.join(owned_file);
+
+    let policies_data = match fs::read_to_string(&policies_path) {
+        Ok(data) => Some(data),
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => None,
+        Err(why) => return Err(why).wrap_err("Failed to read existing Brave policies")
+    };
+    let existing_policies = match &policies_data {
+        Some(data) => serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(data)
+            .wrap_err("Failed to parse existing Brave policies")?,
+        None => serde_json::Map::new()
+    };
+    let owned = policy_merge::read_owned(&owned_path)?;
+
+    let merged = policy_merge::merge(&existing_policies, validated_policies()?, &owned);
+    merged.report("Brave policies");
+
+    if merged.policies == existing_policies && merged.owned == owned {
+        tracing::debug!("Brave policies are already up to date");
+        return Ok(Vec::new());
     }
 
-    if let Some(d) = &policies_data
-        && should_backup
-        && new_policies != existing_policies
+    // Policies are world readable, so the backup doesn't need elevated permissions
+    if let Some(slot) = backup
+        && let Some(d) = policies_data
     {
-        let target =
-            root.join(format!("policies-{}.json", chrono::Utc::now().format("%Y%m%d%H%M")));
-        if let Err(why) = fs::write(&target, d) {
-            warn!(err = ?why, "Failed to backup existing Brave policy file");
-        } else {
-            success(&format!("Backed up existing Brave policies file"));
-        }
+        let target = slot.save("custom-policy.json", d, Some(&policies_path))?;
+        tracing::debug!("Backed up Brave policies to {}", target.display());
+    }
+
+    Ok(vec![
+        PrivilegedWrite::new(policies_path, serde_json::to_string(&merged.policies)?),
+        PrivilegedWrite::new(owned_path, policy_merge::serialize_owned(&merged.owned)?),
+    ])
+}
+
+// The directory Brave reads a level from, and where we keep the keys we own there
+const fn linux_level(enforcement: Enforcement) -> (&'static str, &'static str) {
+    match enforcement {
+        Enforcement::Recommended => ("recommended", "clenzy-owned-recommended-policies.json"),
+        Enforcement::Managed | Enforcement::Locked => ("managed", "clenzy-owned-policies.json")
+    }
+}
+
+// Removes every policy we own from a level we no longer write to
+#[cfg(target_os = "linux")]
+fn released_linux_policies(
+    base: &Path,
+    enforcement: Enforcement
+) -> color_eyre::Result<Vec<PrivilegedWrite>> {
+    use crate::util::policy_merge;
+
+    let (dir, owned_file) = linux_level(enforcement);
+    let owned_path = base.join(owned_file);
+    let owned = policy_merge::read_owned(&owned_path)?;
+    if owned.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let policies_path = base.join(dir).join("custom-policy.json");
+    let existing = match fs::read(&policies_path) {
+        Ok(data) => serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&data)
+            .wrap_err("Failed to parse existing Brave policies")?,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => serde_json::Map::new(),
+        Err(why) => return Err(why).wrap_err("Failed to read existing Brave policies")
+    };
+
+    let released = policy_merge::merge(&existing, &serde_json::Map::new(), &owned);
+    released.report("Brave policies");
+
+    let mut files = Vec::with_capacity(2);
+    if released.policies != existing {
+        files.push(PrivilegedWrite::new(policies_path, serde_json::to_string(&released.policies)?));
+    }
+    files.push(PrivilegedWrite::new(owned_path, policy_merge::serialize_owned(&released.owned)?));
+    Ok(files)
+}
+
+// The file our policies are written to for a packaging, if it reads any
+pub fn linux_policy_file(installed_via: InstalledVia, enforcement: Enforcement) -> Option<PathBuf> {
+    let (dir, _) = linux_level(enforcement);
+    Some(linux_base(installed_via)?.join(dir).join("custom-policy.json"))
+}
+
+// Returns the file this installation will read our policies from, if it has all of them
+#[cfg(target_os = "linux")]
+pub fn verify_linux_policies(installation: &Installation) -> color_eyre::Result<PathBuf> {
+    let Some(path) = linux_policy_file(installation.installed_via, args().enforcement) else {
+        bail!("the {} package can't read policies from the host", installation.installed_via);
+    };
+
+    if !path.exists() {
+        bail!("{} doesn't exist", path.display());
+    }
+
+    let data = fs::read(&path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let policies = serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&data)
+        .wrap_err_with(|| format!("Failed to parse {}", path.display()))?;
+    let missing = validated_policies()?.keys().filter(|k| !policies.contains_key(*k)).count();
+    if missing > 0 {
+        bail!("{} is missing {missing} of clenzy's policies", path.display());
+    }
+
+    Ok(path)
+}
+
+// Every source of Brave policies on this system, named for reporting
+#[cfg(target_os = "linux")]
+pub fn installed_policies() -> Vec<PolicySource> {
+    let bases = [InstalledVia::Local, InstalledVia::Flatpak].into_iter().filter_map(linux_base);
+    let mut files = bases
+        .flat_map(|base| ["managed", "recommended"].map(|dir| base.join(dir)))
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|path| {
+            let policies = fs::read(&path).wrap_err("Failed to read policies").and_then(|data| {
+                serde_json::from_slice(&data).wrap_err("Failed to parse policies")
+            });
+            (path.display().to_string(), policies)
+        })
+        .collect()
+}
+
+#[cfg(target_os = "windows")]
+pub fn installed_policies() -> Vec<PolicySource> {
+    use windows_registry::{LOCAL_MACHINE, Value};
+
+    const KEY: &str = "Software\\Policies\\BraveSoftware\\Brave";
+    let Ok(key) = LOCAL_MACHINE.open(KEY) else {
+        return Vec::new();
+    };
+
+    let policies = key.values().wrap_err("Failed to read policy values").map(|values| {
+        let mut policies = values
+            .filter_map(|(name, value): (String, Value)| {
+                let value = if let Ok(n) = TryInto::<u32>::try_into(value.clone()) {
+                    serde_json::Value::from(n)
+                } else {
+                    serde_json::Value::from(TryInto::<String>::try_into(value).ok()?)
+                };
+                Some((name, value))
+            })
+            .collect();
+
+        // The registry has no booleans, Brave reads 0 and 1 as them
+        policy_schema::coerce_chromium(&mut policies);
+        policies
+    });
+
+    vec![(format!("HKLM\\{KEY}"), policies)]
+}
+
+#[cfg(target_os = "macos")]
+pub fn installed_policies() -> Vec<PolicySource> {
+    let path = std::path::Path::new("/Library/Managed Preferences/com.brave.Browser.plist");
+    if !path.exists() {
+        return Vec::new();
     }
 
-    fs::write(&policies_path, serde_json::to_string(&new_policies)?)
-        .wrap_err_with(|| format!("Failed to write policies to {}", policies_path.display()))
-        .map_err(Into::into)
+    let policies = plist::from_file(path).wrap_err("Failed to parse policies");
+    vec![(path.display().to_string(), policies)]
 }
 
 #[cfg(not(target_os = "macos"))]
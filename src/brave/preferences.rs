@@ -1,37 +1,34 @@
-use crate::{s, util::{get_or_insert_obj, timestamp}};
+use crate::{s, util::get_or_insert_obj};
 use color_eyre::eyre::{bail, ContextCompat, WrapErr};
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 use std::{fs, path::Path};
-use tracing::debug;
-use crate::util::args;
-use crate::util::logging::success;
+use crate::util::{args, content_settings, hardening, transaction::Transaction};
 
-#[allow(clippy::cognitive_complexity, clippy::too_many_lines)]
-pub fn preferences(root: &Path) -> color_eyre::Result<()> {
+pub fn preferences(root: &Path, transaction: &mut Transaction) -> color_eyre::Result<()> {
     let path = root.join("Preferences");
 
-    if args().backup {
-        let backup = root.join(format!("Preferences-{}", timestamp())).with_extension("bak");
-
-        fs::copy(&path, &backup)?;
-        success("Backed up Brave preferences file");
-        debug!("Backup file path: {}", backup.display());
-    }
-
     let prefs_str = fs::read_to_string(&path);
     let Value::Object(mut prefs) = serde_json::from_str::<Value>(&prefs_str?)? else {
         bail!("Failed to cast preferences to an object");
     };
 
-    if let Some(bookmark_bar) = get_or_insert_obj(&mut prefs, "bookmark_bar") {
+    apply_preferences(&mut prefs)?;
+
+    let prefs_str = serde_json::to_string(&prefs)?;
+    transaction
+        .write(&path, prefs_str)
+        .wrap_err_with(|| format!("failed to write preferences to {}", path.display()))
+}
+
+// The pref set itself, shared with initial_preferences so new profiles get it too
+#[allow(clippy::cognitive_complexity, clippy::too_many_lines)]
+pub fn apply_preferences(prefs: &mut Map<String, Value>) -> color_eyre::Result<()> {
+    if let Some(bookmark_bar) = get_or_insert_obj(prefs, "bookmark_bar") {
         bookmark_bar.insert(s!("show_on_all_tabs"), json!(false));
         bookmark_bar.insert(s!("show_tab_groups"), json!(false));
     }
 
-    let brave = prefs
-        .get_mut("brave")
-        .and_then(Value::as_object_mut)
-        .wrap_err("failed to get brave object")?;
+    let brave = get_or_insert_obj(prefs, "brave").wrap_err("failed to get brave object")?;
 
     if let Some(ai_chat) = get_or_insert_obj(brave, "ai_chat") {
         ai_chat.insert(s!("autocomplete_provider_enabled"), json!(false));
@@ -140,13 +137,13 @@ pub fn preferences(root: &Path) -> color_eyre::Result<()> {
 
     // -- END BRAVE MAP SECTION --
 
-    if let Some(custom_links) = get_or_insert_obj(&mut prefs, "custom_links") {
+    if let Some(custom_links) = get_or_insert_obj(prefs, "custom_links") {
         custom_links.insert(s!("initialized"), json!(true));
     }
 
     prefs.insert(s!("enable_do_not_track"), json!(true));
 
-    if let Some(in_product_help) = get_or_insert_obj(&mut prefs, "in_product_help") {
+    if let Some(in_product_help) = get_or_insert_obj(prefs, "in_product_help") {
         if let Some(new_badge) = get_or_insert_obj(in_product_help, "new_badge") {
             if let Some(compose_nudge) = get_or_insert_obj(new_badge, "ComposeNudge") {
                 compose_nudge.insert(s!("show_count"), json!(0));
@@ -166,22 +163,22 @@ pub fn preferences(root: &Path) -> color_eyre::Result<()> {
         }
     }
 
-    if let Some(ntp) = get_or_insert_obj(&mut prefs, "ntp") {
+    if let Some(ntp) = get_or_insert_obj(prefs, "ntp") {
         ntp.insert(s!("shortcust_visible"), json!(false));
         ntp.insert(s!("use_most_visited_tiles"), json!(false));
     }
 
-    if let Some(omnibox) = get_or_insert_obj(&mut prefs, "omnibox") {
+    if let Some(omnibox) = get_or_insert_obj(prefs, "omnibox") {
         // show the entire URL always
         omnibox.insert(s!("prevent_url_elisions"), json!(true));
         omnibox.insert(s!("shown_count_history_scope_promo"), json!(false));
     }
 
-    if let Some(search) = get_or_insert_obj(&mut prefs, "search") {
+    if let Some(search) = get_or_insert_obj(prefs, "search") {
         search.insert(s!("suggest_enabled"), json!(args().search_suggestions));
     }
 
-    if let Some(privacy_sandbox) = get_or_insert_obj(&mut prefs, "privacy_sandbox") {
+    if let Some(privacy_sandbox) = get_or_insert_obj(prefs, "privacy_sandbox") {
         privacy_sandbox.insert(s!("first_party_sets_enabled"), json!(false));
         if let Some(m1) = get_or_insert_obj(privacy_sandbox, "m1") {
             m1.insert(s!("ad_measurement_enabled"), json!(false));
@@ -190,7 +187,6 @@ pub fn preferences(root: &Path) -> color_eyre::Result<()> {
         }
     }
 
-    let prefs_str = serde_json::to_string(&prefs)?;
-    fs::write(&path, prefs_str)
-        .wrap_err_with(|| format!("failed to write preferences to {}", path.display()))
+    content_settings::apply_chromium_preferences(prefs)?;
+    hardening::apply_chromium_preferences(prefs)
 }
@@ -41,8 +41,9 @@ pub static REMOVE_ENABLED_FEATURES: LazyLock<Vec<&str>> = LazyLock::new(|| {
     lines
 });
 
-pub static REMOVE_ENABLED_LAB_FEATURES: LazyLock<Vec<&str>> = LazyLock::new(|| {
-    include_str!("../../snippets/brave/remove_lab_experiments_features")
+// brave://flags experiments as name@option, where 1 is usually enabled and 2 disabled
+pub static LAB_EXPERIMENTS: LazyLock<Vec<&str>> = LazyLock::new(|| {
+    include_str!("../../snippets/brave/lab_experiments")
         .lines()
         .filter(|line| !line.is_empty())
         .collect()
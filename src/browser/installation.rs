@@ -1,4 +1,4 @@
-use crate::browser::Browser;
+use crate::{browser::Browser, util::transaction::Transaction};
 use std::{fmt::Display, path::PathBuf};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -11,14 +11,16 @@ pub enum InstalledVia {
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Variant {
     Nightly,
-    Beta
+    Beta,
+    Esr,
+    DeveloperEdition
 }
 
 #[derive(Clone, Debug)]
 pub struct Installation {
     // TODO see which we can make private & remove clippy allow
     pub browser_name: &'static str,
-    pub debloat_fn: fn(&Installation) -> color_eyre::Result<()>,
+    pub debloat_fn: fn(&Self, &mut Transaction) -> color_eyre::Result<()>,
     pub installed_via: InstalledVia,
     pub data_folders: Vec<PathBuf>,
     pub app_folders: Vec<PathBuf>,
@@ -30,8 +32,11 @@ impl Installation {
         InstallationBuilder::new::<B>()
     }
 
+    // Nothing is written unless every step for the installation succeeds
     pub fn debloat(&self) -> color_eyre::Result<()> {
-        (self.debloat_fn)(self)
+        let mut transaction = Transaction::new();
+        (self.debloat_fn)(self, &mut transaction)?;
+        transaction.commit()
     }
 
     pub const fn is_valid(&self) -> bool {
@@ -41,7 +46,7 @@ impl Installation {
 
 pub struct InstallationBuilder {
     browser_name: &'static str,
-    debloat_fn: fn(&Installation) -> color_eyre::Result<()>,
+    debloat_fn: fn(&Installation, &mut Transaction) -> color_eyre::Result<()>,
     installed_via: Option<InstalledVia>,
     data_folders: Vec<PathBuf>,
     app_folders: Vec<PathBuf>,
@@ -134,6 +139,8 @@ impl Display for Variant {
         match self {
             Self::Nightly => write!(f, "Nightly"),
             Self::Beta => write!(f, "Beta"),
+            Self::Esr => write!(f, "ESR"),
+            Self::DeveloperEdition => write!(f, "Developer Edition")
         }
     }
 }
@@ -1,4 +1,4 @@
-use crate::browser::installation::Installation;
+use crate::{browser::installation::Installation, util::transaction::Transaction};
 
 pub mod profile;
 pub mod installation;
@@ -9,5 +9,8 @@ pub trait Browser {
     fn fetch_resources() -> Option<fn() -> color_eyre::Result<&'static str>> {
         None
     }
-    fn debloat(installation: &Installation) -> color_eyre::Result<()>;
+    fn debloat(
+        installation: &Installation,
+        transaction: &mut Transaction
+    ) -> color_eyre::Result<()>;
 }
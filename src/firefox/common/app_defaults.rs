@@ -0,0 +1,238 @@
+use crate::{
+    AppDefaults, Enforcement, browser::{
+        Browser, installation::{Installation, InstalledVia}
+    }, firefox::{Firefox, common::prefs::{PrefValue, parse_prefs}}, util::{args, is_permission_denied, logging::success, transaction::write_atomic}, zen::Zen
+};
+#[cfg(target_os = "linux")]
+use crate::util::elevate::{self, PrivilegedWrite};
+use color_eyre::eyre::{WrapErr, bail};
+use ini::Ini;
+use std::{
+    fmt::Write, fs, path::{Path, PathBuf}
+};
+use tracing::{debug, warn};
+
+const AUTOCONFIG_FILE: &str = "clenzy.cfg";
+const AUTOCONFIG_PREFS_FILE: &str = "defaults/pref/clenzy.js";
+const DISTRIBUTION_FILE: &str = "distribution/distribution.ini";
+const DISTRIBUTION_ID: &str = "clenzy";
+
+struct AppFile {
+    // Relative to the app's resources directory
+    path: &'static str,
+    contents: String
+}
+
+// Installs our prefs into the app folders, so profiles created after clenzy ran start debloated too
+pub fn install(installation: &Installation, user_js: &str) -> color_eyre::Result<()> {
+    if args().app_defaults.is_empty() {
+        return Ok(());
+    }
+
+    if installation.installed_via != InstalledVia::Local {
+        warn!("{installation} is installed read-only, skipping app defaults");
+        return Ok(());
+    }
+
+    if installation.app_folders.is_empty() {
+        warn!("No app folders found for {installation}, skipping app defaults");
+        return Ok(());
+    }
+
+    let mut denied = Vec::new();
+    for root in installation.app_folders.iter().map(|f| resources_dir(f)) {
+        let files = app_files(&root, user_js)?;
+        match write_files(&root, &files) {
+            Ok(()) => success(&format!("Installed app defaults into {}", root.display())),
+            Err(why) if is_permission_denied(&why) => {
+                debug!(err = ?why, path = %root.display(), "App folder isn't writable");
+                denied.push(root);
+            }
+            Err(why) => return Err(why)
+        }
+    }
+
+    if denied.is_empty() {
+        return Ok(());
+    }
+
+    install_with_elevation(&denied, user_js)
+}
+
+#[cfg(target_os = "linux")]
+fn install_with_elevation(denied: &[PathBuf], user_js: &str) -> color_eyre::Result<()> {
+    let mut files = Vec::new();
+    for root in denied {
+        files.extend(
+            app_files(root, user_js)?
+                .into_iter()
+                .map(|file| PrivilegedWrite::new(root.join(file.path), file.contents))
+        );
+    }
+
+    if !elevate::write_elevated("Installing app defaults", &files)? {
+        return Ok(());
+    }
+
+    for root in denied {
+        verify(root, &app_files(root, user_js)?)?;
+        success(&format!("Installed app defaults into {}", root.display()));
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn install_with_elevation(denied: &[PathBuf], user_js: &str) -> color_eyre::Result<()> {
+    if !crate::util::should_elevate("Installing app defaults") {
+        return Ok(());
+    }
+
+    crate::util::elevate_and_run("--app-defaults-elevated")?;
+
+    // The elevated child writes every app folder it finds, so make sure it got ours
+    for root in denied {
+        verify(root, &app_files(root, user_js)?)?;
+        success(&format!("Installed app defaults into {}", root.display()));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn install_with_elevation(denied: &[PathBuf], _user_js: &str) -> color_eyre::Result<()> {
+    for root in denied {
+        warn!("{} isn't writable, run clenzy with sudo to install app defaults", root.display());
+    }
+
+    Ok(())
+}
+
+// Runs as the elevated child, nothing here may prompt
+pub fn install_elevated(installations: &[&Installation]) -> color_eyre::Result<()> {
+    for installation in installations {
+        if installation.installed_via != InstalledVia::Local || installation.app_folders.is_empty()
+        {
+            continue;
+        }
+
+        let user_js = if installation.browser_name == Firefox::name() {
+            crate::firefox::configured_user_js()?
+        } else if installation.browser_name == Zen::name() {
+            crate::zen::configured_user_js()?
+        } else {
+            continue;
+        };
+
+        for root in installation.app_folders.iter().map(|f| resources_dir(f)) {
+            write_files(&root, &app_files(&root, &user_js)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+// The app folder of a macOS bundle is `Contents`, but Firefox reads defaults from `Resources`
+pub fn resources_dir(app_folder: &Path) -> PathBuf {
+    if cfg!(target_os = "macos") {
+        app_folder.join("Resources")
+    } else {
+        app_folder.to_path_buf()
+    }
+}
+
+fn app_files(root: &Path, user_js: &str) -> color_eyre::Result<Vec<AppFile>> {
+    let prefs = parse_prefs(user_js);
+    if prefs.is_empty() {
+        bail!("Found no prefs to install");
+    }
+
+    let mut files = Vec::with_capacity(3);
+    if args().app_defaults.contains(&AppDefaults::Autoconfig) {
+        let lock = args().lock_prefs || args().enforcement == Enforcement::Locked;
+        let function = if lock { "lockPref" } else { "defaultPref" };
+
+        // Firefox always skips the first line of an autoconfig file
+        let mut cfg = String::from("// Installed by clenzy\n");
+        for pref in &prefs {
+            let name = PrefValue::String(pref.name.clone());
+            let _ = writeln!(cfg, "{function}({name}, {});", pref.value);
+        }
+
+        files.push(AppFile {
+            path: AUTOCONFIG_PREFS_FILE,
+            contents: format!(
+                "// Installed by clenzy, loads {AUTOCONFIG_FILE} on startup\n\
+                 pref(\"general.config.filename\", \"{AUTOCONFIG_FILE}\");\n\
+                 pref(\"general.config.obscure_value\", 0);\n"
+            )
+        });
+        files.push(AppFile { path: AUTOCONFIG_FILE, contents: cfg });
+    }
+
+    if args().app_defaults.contains(&AppDefaults::Distribution) {
+        if is_foreign_distribution(&root.join(DISTRIBUTION_FILE)) {
+            warn!(
+                "{} already has a distribution.ini from someone else, not replacing it",
+                root.display()
+            );
+        } else {
+            let mut ini = format!(
+                "[Global]\nid={DISTRIBUTION_ID}\nversion={}\nabout=Defaults installed by clenzy\n\n\
+                 [Preferences]\n",
+                env!("CARGO_PKG_VERSION")
+            );
+            for pref in &prefs {
+                let _ = writeln!(ini, "{}={}", pref.name, pref.value);
+            }
+
+            files.push(AppFile { path: DISTRIBUTION_FILE, contents: ini });
+        }
+    }
+
+    Ok(files)
+}
+
+// Linux distributions ship their own distribution.ini, which we must not clobber
+fn is_foreign_distribution(path: &Path) -> bool {
+    if !path.exists() {
+        return false;
+    }
+
+    let Ok(ini) = Ini::load_from_file(path) else {
+        return true;
+    };
+
+    ini.get_from(Some("Global"), "id") != Some(DISTRIBUTION_ID)
+}
+
+fn write_files(root: &Path, files: &[AppFile]) -> color_eyre::Result<()> {
+    for file in files {
+        let path = root.join(file.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        write_atomic(&path, &file.contents)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+        debug!(path = %path.display(), "Wrote app default");
+    }
+
+    verify(root, files)
+}
+
+// Reads every file back, so a silently redirected or read-only write doesn't go unnoticed
+fn verify(root: &Path, files: &[AppFile]) -> color_eyre::Result<()> {
+    for file in files {
+        let path = root.join(file.path);
+        let written = fs::read_to_string(&path)
+            .wrap_err_with(|| format!("Failed to read back {}", path.display()))?;
+
+        if written != file.contents {
+            bail!("{} doesn't contain the app defaults that were written", path.display());
+        }
+    }
+
+    Ok(())
+}
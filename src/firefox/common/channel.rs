@@ -0,0 +1,68 @@
+use crate::browser::installation::Variant;
+use std::{fs, path::Path};
+use tracing::debug;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Release,
+    Esr,
+    Beta,
+    // Developer Edition
+    Aurora,
+    Nightly
+}
+
+impl Channel {
+    pub const fn variant(self) -> Option<Variant> {
+        match self {
+            Self::Release => None,
+            Self::Esr => Some(Variant::Esr),
+            Self::Beta => Some(Variant::Beta),
+            Self::Aurora => Some(Variant::DeveloperEdition),
+            Self::Nightly => Some(Variant::Nightly)
+        }
+    }
+
+    fn parse(channel: &str) -> Option<Self> {
+        match channel.trim().to_lowercase().as_str() {
+            "release" | "default" => Some(Self::Release),
+            "esr" => Some(Self::Esr),
+            "beta" => Some(Self::Beta),
+            "aurora" => Some(Self::Aurora),
+            "nightly" => Some(Self::Nightly),
+            _ => None
+        }
+    }
+
+    // Firefox names dedicated profiles after the channel of the install that created them,
+    // e.g. `abcd1234.default-release`, `abcd1234.default-esr` or `abcd1234.dev-edition-default`.
+    pub fn from_profile_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with("dev-edition-default") {
+            return Some(Self::Aurora);
+        }
+
+        let (_, suffix) = name.rsplit_once(".default-")?;
+        Self::parse(suffix)
+    }
+
+    // Every build ships `app.update.channel` in defaults/pref/channel-prefs.js
+    pub fn from_app_folder(app_folder: &Path) -> Option<Self> {
+        let channel_prefs =
+            ["defaults/pref/channel-prefs.js", "Resources/defaults/pref/channel-prefs.js"]
+                .iter()
+                .map(|p| app_folder.join(p))
+                .find(|p| p.exists())?;
+
+        let content = fs::read_to_string(&channel_prefs).ok()?;
+        let channel = content
+            .lines()
+            .map(str::trim)
+            .find(|l| l.starts_with("pref(\"app.update.channel\""))?
+            .split('"')
+            .nth(3)?;
+
+        debug!(path = %channel_prefs.display(), %channel, "Read update channel");
+        Self::parse(channel)
+    }
+}
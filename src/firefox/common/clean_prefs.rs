@@ -0,0 +1,155 @@
+use crate::{
+    browser::{Browser, installation::Installation, profile::BrowserProfile}, firefox::{Firefox, common::{prefs::{Pref, parse_prefs}, profiles::get_profiles, user_js}}, util::{UnwrapOrExit, args, backups::Slot, lock::{LockKind, ensure_unlocked}, logging::success, transaction::Transaction}, zen::Zen
+};
+use color_eyre::eyre::{bail, WrapErr};
+use std::{collections::HashSet, fs, path::Path};
+use tracing::{debug, info, warn};
+
+// Removing a pref from user.js doesn't reset it, Firefox keeps the last value in prefs.js. This
+// takes out the prefs an older user.js from clenzy set that the current one doesn't anymore.
+pub fn clean_command(
+    installations: &[&Installation],
+    user_js: Option<&Path>
+) -> color_eyre::Result<()> {
+    let file = user_js
+        .map(|path| {
+            fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))
+        })
+        .transpose()?;
+
+    let mut checked = 0;
+    let mut cleaned = 0;
+    for installation in installations {
+        let current = if installation.browser_name == Firefox::name() {
+            file.clone().map_or_else(crate::firefox::configured_user_js, Ok)?
+        } else if installation.browser_name == Zen::name() {
+            file.clone().map_or_else(crate::zen::configured_user_js, Ok)?
+        } else {
+            continue;
+        };
+
+        let app_folders = installations
+            .iter()
+            .filter(|i| i.browser_name == installation.browser_name)
+            .flat_map(|i| i.app_folders.clone())
+            .collect::<Vec<_>>();
+        for data_folder in &installation.data_folders {
+            let profiles = get_profiles(data_folder, installation.variant, &app_folders);
+            let profiles = match profiles {
+                Ok((_, profiles)) => profiles,
+                Err(why) => {
+                    warn!(err = ?why, "Failed to read profiles in {}", data_folder.display());
+                    continue;
+                }
+            };
+
+            for profile in &profiles {
+                checked += 1;
+                match clean_profile(installation, profile, &current) {
+                    Ok(true) => cleaned += 1,
+                    Ok(false) => {}
+                    Err(why) => warn!(err = ?why, "Failed to clean prefs.js of {profile}")
+                }
+            }
+        }
+    }
+
+    if checked == 0 {
+        info!("No Firefox or Zen profiles found");
+    } else if cleaned == 0 {
+        info!("Checked {checked} profiles, none of them were changed");
+    }
+
+    Ok(())
+}
+
+// Returns whether prefs.js was rewritten
+fn clean_profile(
+    installation: &Installation,
+    profile: &BrowserProfile,
+    current: &str
+) -> color_eyre::Result<bool> {
+    // Firefox writes prefs.js when it exits, which would put everything back
+    if !ensure_unlocked(LockKind::Firefox, &profile.path, profile) {
+        bail!("Profile is in use");
+    }
+
+    let prefs_path = profile.path.join("prefs.js");
+    let prefs_js = match fs::read_to_string(&prefs_path) {
+        Ok(prefs_js) => prefs_js,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => {
+            debug!("{profile} has no prefs.js yet");
+            return Ok(false);
+        }
+        Err(why) => return Err(why).wrap_err("Failed to read prefs.js")
+    };
+
+    let slot = Slot::profile(installation, &profile.path);
+    // Backups also hold the user.js from before clenzy replaced it, those prefs are the user's
+    let previous = slot
+        .history("user.js")?
+        .into_iter()
+        .map(|contents| String::from_utf8_lossy(&contents).into_owned())
+        .filter(|user_js| user_js::is_ours(user_js))
+        .collect::<Vec<_>>();
+    if previous.is_empty() {
+        info!("{profile}: no earlier user.js from clenzy in the backups to compare against");
+        return Ok(false);
+    }
+
+    // A user.js in the profile that isn't ours still sets its prefs, so leave those alone
+    let installed = fs::read_to_string(profile.path.join("user.js")).unwrap_or_default();
+    let stale = stale_prefs(&[current, &installed], &previous);
+
+    let (kept, removed): (Vec<_>, Vec<_>) =
+        prefs_js.lines().partition(|line| !parse_prefs(line).iter().any(|p| stale.contains(p)));
+    if removed.is_empty() {
+        info!("{profile}: prefs.js has nothing clenzy stopped setting");
+        return Ok(false);
+    }
+
+    info!("{profile}: {} prefs clenzy no longer sets are still in prefs.js:", removed.len());
+    for line in &removed {
+        info!("  {}", line.trim());
+    }
+
+    let confirmed = args().auto_confirm
+        || inquire::Confirm::new(&format!("Remove them from prefs.js of {profile}? (y/n)"))
+            .prompt()
+            .unwrap_or_exit();
+    if !confirmed {
+        return Ok(false);
+    }
+
+    if args().backup {
+        slot.save("prefs.js", &prefs_js, Some(&prefs_path))
+            .wrap_err("Failed to backup prefs.js")?;
+    }
+
+    let mut cleaned = kept.join("\n");
+    cleaned.push('\n');
+
+    let mut transaction = Transaction::new();
+    transaction.write(&prefs_path, cleaned).wrap_err("Failed to write prefs.js")?;
+    transaction.commit()?;
+
+    success(&format!("Removed {} prefs from prefs.js of {profile}", removed.len()));
+    Ok(true)
+}
+
+// Prefs an earlier user.js set that no current one does. Only their old values count, a value
+// changed since then in about:config is the user's own.
+fn stale_prefs(current: &[&str], previous: &[String]) -> Vec<Pref> {
+    let current = current
+        .iter()
+        .flat_map(|user_js| parse_prefs(user_js))
+        .map(|p| p.name)
+        .collect::<HashSet<_>>();
+
+    let mut seen = HashSet::new();
+    previous
+        .iter()
+        .flat_map(|user_js| parse_prefs(user_js))
+        .filter(|p| !current.contains(&p.name) && seen.insert(p.clone()))
+        .collect()
+}
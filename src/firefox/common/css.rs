@@ -0,0 +1,179 @@
+use crate::{
+    CssPreset, browser::profile::BrowserProfile, util::{args, transaction::Transaction}
+};
+use color_eyre::eyre::{bail, WrapErr};
+use std::{fs, path::Path};
+use tracing::debug;
+
+const SECTION_START: &str =
+    "/* BEGIN clenzy: rewritten on every run, add your own rules outside of this section */";
+const SECTION_END: &str = "/* END clenzy */";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Sheet {
+    // The browser's own UI
+    Chrome,
+    // Web pages and about: pages
+    Content
+}
+
+impl Sheet {
+    const fn file_name(self) -> &'static str {
+        match self {
+            Self::Chrome => "userChrome.css",
+            Self::Content => "userContent.css"
+        }
+    }
+}
+
+impl CssPreset {
+    const fn sheet(self) -> Sheet {
+        match self {
+            Self::HideNewTabLogo => Sheet::Content,
+            _ => Sheet::Chrome
+        }
+    }
+
+    const fn css(self) -> &'static str {
+        match self {
+            Self::HideTabClose => {
+                include_str!("../../../snippets/firefox_common/css/hide_tab_close.css")
+            }
+            Self::HideFirefoxView => {
+                include_str!("../../../snippets/firefox_common/css/hide_firefox_view.css")
+            }
+            Self::HideAllTabsButton => {
+                include_str!("../../../snippets/firefox_common/css/hide_all_tabs_button.css")
+            }
+            Self::HidePrivateBrowsingIndicator => include_str!(
+                "../../../snippets/firefox_common/css/hide_private_browsing_indicator.css"
+            ),
+            Self::HideNewTabLogo => {
+                include_str!("../../../snippets/firefox_common/css/hide_new_tab_logo.css")
+            }
+        }
+    }
+}
+
+// Whether any CSS was asked for, stylesheets are only turned on then
+pub fn is_enabled() -> bool {
+    !args().css_presets.is_empty()
+        || !args().user_chrome.is_empty()
+        || !args().user_content.is_empty()
+}
+
+// Puts our presets and the user's CSS files into the profile's chrome folder, leaving every rule
+// outside of our section alone. Without any CSS asked for, our section is taken out again.
+pub fn install(profile: &BrowserProfile, transaction: &mut Transaction) -> color_eyre::Result<()> {
+    let chrome = profile.path.join("chrome");
+    for sheet in [Sheet::Chrome, Sheet::Content] {
+        let path = chrome.join(sheet.file_name());
+        let existing = match fs::read_to_string(&path) {
+            Ok(existing) => existing,
+            Err(why) if why.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(why) => {
+                return Err(why).wrap_err_with(|| format!("Failed to read {}", path.display()));
+            }
+        };
+
+        let updated = replace_section(&existing, &rules(sheet)?)
+            .wrap_err_with(|| format!("Failed to update {}", path.display()))?;
+        if updated == existing {
+            debug!("{} is already up to date", sheet.file_name());
+            continue;
+        }
+
+        if updated.trim().is_empty() {
+            debug!("Removing {}, only our section was in it", sheet.file_name());
+            transaction.remove(&path)?;
+            continue;
+        }
+
+        fs::create_dir_all(&chrome)
+            .wrap_err_with(|| format!("Failed to create {}", chrome.display()))?;
+        transaction
+            .write(&path, updated)
+            .wrap_err_with(|| format!("Failed to write {}", sheet.file_name()))?;
+        debug!("Updated {}", sheet.file_name());
+    }
+
+    Ok(())
+}
+
+// Our section of each stylesheet that has one, named after its file
+pub fn sections() -> color_eyre::Result<Vec<(&'static str, String)>> {
+    let mut sections = Vec::new();
+    for sheet in [Sheet::Chrome, Sheet::Content] {
+        let rules = rules(sheet)?;
+        if !rules.is_empty() {
+            sections.push((sheet.file_name(), replace_section("", &rules)?));
+        }
+    }
+
+    Ok(sections)
+}
+
+// Presets first, then the user's files in the order they were given
+fn rules(sheet: Sheet) -> color_eyre::Result<String> {
+    let files = match sheet {
+        Sheet::Chrome => &args().user_chrome,
+        Sheet::Content => &args().user_content
+    };
+
+    let mut rules = args()
+        .css_presets
+        .iter()
+        .filter(|p| p.sheet() == sheet)
+        .map(|p| p.css().trim().to_owned())
+        .collect::<Vec<_>>();
+    for file in files {
+        rules.push(read_css(file)?.trim().to_owned());
+    }
+
+    Ok(rules.join("\n\n"))
+}
+
+fn read_css(path: &Path) -> color_eyre::Result<String> {
+    fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))
+}
+
+// Swaps our section for `rules` where it was, or adds it at the end, since @import and
+// @namespace rules only work at the top
+fn replace_section(existing: &str, rules: &str) -> color_eyre::Result<String> {
+    let lines = existing.lines().collect::<Vec<_>>();
+    let start = lines.iter().position(|l| l.trim() == SECTION_START);
+    let end = lines.iter().position(|l| l.trim() == SECTION_END);
+    if start.is_none() && end.is_none() && rules.is_empty() {
+        return Ok(existing.to_owned());
+    }
+
+    let (before, after) = match (start, end) {
+        (Some(start), Some(end)) if start < end => {
+            (lines.get(..start).unwrap_or_default(), lines.get(end + 1..).unwrap_or_default())
+        }
+        (None, None) => (lines.as_slice(), [].as_slice()),
+        // Guessing where it ends could delete the user's rules
+        _ => bail!("clenzy's section isn't closed, fix or remove it by hand")
+    };
+
+    let mut parts = Vec::new();
+    let before = before.join("\n");
+    if !before.trim().is_empty() {
+        parts.push(before.trim_end().to_owned());
+    }
+    if !rules.is_empty() {
+        parts.push(format!("{SECTION_START}\n{rules}\n{SECTION_END}"));
+    }
+    let after = after.join("\n");
+    if !after.trim().is_empty() {
+        parts.push(after.trim().to_owned());
+    }
+
+    if parts.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut css = parts.join("\n\n");
+    css.push('\n');
+    Ok(css)
+}
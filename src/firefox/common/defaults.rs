@@ -0,0 +1,246 @@
+use crate::{
+    browser::{Browser, installation::Installation}, firefox::{Firefox, common::{app_defaults::resources_dir, prefs::{Pref, PrefValue, parse_prefs}}}, util::{binary, logging::success}, zen::Zen
+};
+use color_eyre::eyre::WrapErr;
+use std::{
+    collections::{HashMap, HashSet}, fs::File, io::{BufReader, Read}, path::{Path, PathBuf}
+};
+use tracing::{debug, info, warn};
+use zip::ZipArchive;
+
+// The default prefs of an installed build, and every pref its code mentions
+pub struct BuildPrefs {
+    pub sources: Vec<PathBuf>,
+    defaults: HashMap<String, PrefValue>,
+    referenced: HashSet<String>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PrefStatus {
+    // Nothing in the build defines or reads it, so it's probably obsolete
+    Unknown,
+    WrongType(PrefValue),
+    // Already the default, setting it changes nothing
+    Redundant,
+    Changed,
+    // Read by the build without a default in the prefs files, like static prefs
+    NoDefault
+}
+
+impl BuildPrefs {
+    // Reads omni.ja and the main library of the installation, looking for `names`
+    pub fn load(installation: &Installation, names: &[&str]) -> Option<Self> {
+        let omni_jas = installation
+            .app_folders
+            .iter()
+            .map(|f| resources_dir(f))
+            .flat_map(|r| [r.join("omni.ja"), r.join("browser/omni.ja")])
+            .filter(|p| p.is_file())
+            .collect::<Vec<_>>();
+
+        if omni_jas.is_empty() {
+            debug!("Couldn't find omni.ja for {installation}, not checking prefs");
+            return None;
+        }
+
+        match load(&omni_jas, &library_paths(installation), names) {
+            Ok(prefs) => Some(prefs),
+            Err(why) => {
+                warn!(err = ?why, "Failed to read the default prefs of {installation}");
+                None
+            }
+        }
+    }
+
+    pub fn status(&self, pref: &Pref) -> PrefStatus {
+        match self.defaults.get(&pref.name) {
+            Some(default) if *default == pref.value => PrefStatus::Redundant,
+            Some(default) if same_type(default, &pref.value) => PrefStatus::Changed,
+            Some(default) => PrefStatus::WrongType(default.clone()),
+            None if self.referenced.contains(&pref.name) => PrefStatus::NoDefault,
+            None => PrefStatus::Unknown
+        }
+    }
+}
+
+const fn same_type(a: &PrefValue, b: &PrefValue) -> bool {
+    matches!(
+        (a, b),
+        (PrefValue::Bool(_), PrefValue::Bool(_))
+            | (PrefValue::Int(_), PrefValue::Int(_))
+            | (PrefValue::String(_), PrefValue::String(_))
+    )
+}
+
+// Static prefs are compiled into libxul instead of being listed in omni.ja
+fn library_paths(installation: &Installation) -> Vec<PathBuf> {
+    let library = if cfg!(target_os = "windows") {
+        "xul.dll"
+    } else if cfg!(target_os = "macos") {
+        "MacOS/XUL"
+    } else {
+        "libxul.so"
+    };
+
+    installation.app_folders.iter().map(|f| f.join(library)).filter(|p| p.is_file()).collect()
+}
+
+fn load(
+    omni_jas: &[PathBuf],
+    libraries: &[PathBuf],
+    names: &[&str]
+) -> color_eyre::Result<BuildPrefs> {
+    // Code reads prefs by their name in quotes, and libraries store them as C strings
+    let (owners, patterns): (Vec<_>, Vec<_>) = names
+        .iter()
+        .flat_map(|n| [(*n, format!("{n}\"")), (*n, format!("{n}'")), (*n, format!("{n}\0"))])
+        .unzip();
+    let (searcher, longest) = binary::searcher(&patterns)?;
+
+    let mut defaults = HashMap::new();
+    let mut found = HashSet::new();
+    for path in omni_jas {
+        let file =
+            File::open(path).wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+        let mut omni = ZipArchive::new(BufReader::new(file))
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+
+        for i in 0..omni.len() {
+            let mut entry = omni.by_index(i)?;
+            if !entry.is_file() {
+                continue;
+            }
+
+            if is_prefs_file(entry.name()) {
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents)?;
+                defaults.extend(parse_prefs(&contents).into_iter().map(|p| (p.name, p.value)));
+                binary::find_patterns(&mut contents.as_bytes(), &searcher, longest, &mut found)?;
+            } else {
+                binary::find_patterns(&mut entry, &searcher, longest, &mut found)?;
+            }
+        }
+    }
+
+    for path in libraries {
+        let mut file =
+            File::open(path).wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+        binary::find_patterns(&mut file, &searcher, longest, &mut found)?;
+    }
+
+    let referenced =
+        found.into_iter().filter_map(|i| owners.get(i)).map(|n| (*n).to_owned()).collect();
+
+    debug!(defaults = %defaults.len(), "Read default prefs");
+    Ok(BuildPrefs {
+        sources: omni_jas.iter().chain(libraries).cloned().collect(),
+        defaults,
+        referenced
+    })
+}
+
+// greprefs.js holds the platform's defaults, defaults/preferences/*.js the browser's
+fn is_prefs_file(name: &str) -> bool {
+    let is_js = Path::new(name).extension().is_some_and(|e| e.eq_ignore_ascii_case("js"));
+    name == "greprefs.js" || (is_js && name.starts_with("defaults/pref"))
+}
+
+// The prefs in user.js worth mentioning, grouped by what's wrong with them
+#[derive(Default)]
+pub struct Findings<'a> {
+    pub unknown: Vec<&'a Pref>,
+    pub wrong_type: Vec<(&'a Pref, PrefValue)>,
+    pub redundant: Vec<&'a Pref>
+}
+
+impl<'a> Findings<'a> {
+    pub fn new(build: &BuildPrefs, prefs: &'a [Pref]) -> Self {
+        let mut findings = Self::default();
+        for pref in prefs {
+            match build.status(pref) {
+                PrefStatus::Unknown => findings.unknown.push(pref),
+                PrefStatus::WrongType(default) => findings.wrong_type.push((pref, default)),
+                PrefStatus::Redundant => findings.redundant.push(pref),
+                PrefStatus::Changed | PrefStatus::NoDefault => {}
+            }
+        }
+
+        findings
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.unknown.is_empty() && self.wrong_type.is_empty() && self.redundant.is_empty()
+    }
+
+    pub fn log(&self) {
+        if !self.unknown.is_empty() {
+            info!("  Unknown to this build, possibly obsolete ({}):", self.unknown.len());
+            for pref in &self.unknown {
+                info!("    {}", pref.name);
+            }
+        }
+
+        if !self.wrong_type.is_empty() {
+            info!("  A different type than the default ({}):", self.wrong_type.len());
+            for (pref, default) in &self.wrong_type {
+                info!("    {} is {}, the default is {default}", pref.name, pref.value);
+            }
+        }
+
+        if !self.redundant.is_empty() {
+            info!("  Already the default ({}):", self.redundant.len());
+            for pref in &self.redundant {
+                info!("    {} = {}", pref.name, pref.value);
+            }
+        }
+    }
+}
+
+// Checks clenzy's user.js, or the one at `user_js`, against every Firefox and Zen installation
+pub fn check_command(
+    installations: &[&Installation],
+    user_js: Option<&Path>
+) -> color_eyre::Result<()> {
+    let file = user_js
+        .map(|path| {
+            std::fs::read_to_string(path)
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))
+        })
+        .transpose()?;
+
+    let mut checked = 0;
+    for installation in installations.iter().filter(|i| !i.app_folders.is_empty()) {
+        let user_js = if installation.browser_name == Firefox::name() {
+            file.clone().map_or_else(crate::firefox::configured_user_js, Ok)?
+        } else if installation.browser_name == Zen::name() {
+            file.clone().map_or_else(crate::zen::configured_user_js, Ok)?
+        } else {
+            continue;
+        };
+
+        let prefs = parse_prefs(&user_js);
+        let names = prefs.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        let Some(build) = BuildPrefs::load(installation, &names) else {
+            warn!("Couldn't find the default prefs of {installation}");
+            continue;
+        };
+
+        let sources = build.sources.iter().map(|s| s.display().to_string()).collect::<Vec<_>>();
+        info!("{installation}: checked {} prefs against {}", prefs.len(), sources.join(", "));
+
+        let findings = Findings::new(&build, &prefs);
+        if findings.is_empty() {
+            success("  Every pref is known to this build and changes its default");
+        } else {
+            findings.log();
+        }
+
+        checked += 1;
+    }
+
+    if checked == 0 {
+        info!("No Firefox or Zen installations to check prefs against");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,191 @@
+// A straight port of CityHash, so the names and bounds follow the reference implementation. Every
+// fetch is within bounds for the lengths each function is called with.
+#![allow(
+    clippy::many_single_char_names,
+    clippy::indexing_slicing,
+    clippy::cast_possible_truncation
+)]
+
+use std::path::Path;
+
+// CityHash 1.0.3 constants, the version Firefox bundles
+const K0: u64 = 0xc3a5_c85c_97cb_3127;
+const K1: u64 = 0xb492_b66b_e98b_2d1d;
+const K2: u64 = 0x9ae1_6a3b_2f90_404f;
+const K3: u64 = 0xc949_d7c7_509e_6557;
+const K_MUL: u64 = 0x9ddf_ea08_eb38_2d69;
+
+// The name Firefox gives the [Install<hash>] section of an app folder: CityHash64 of the directory
+// holding the executable as UTF-16, in uppercase hex
+pub fn install_hash(app_folder: &Path) -> String {
+    let install_dir =
+        if cfg!(target_os = "macos") { app_folder.join("MacOS") } else { app_folder.to_owned() };
+    let bytes = install_dir
+        .to_string_lossy()
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<_>>();
+
+    format!("{:X}", city_hash64(&bytes))
+}
+
+fn fetch64(s: &[u8], i: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&s[i..i + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn fetch32(s: &[u8], i: usize) -> u64 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&s[i..i + 4]);
+    u64::from(u32::from_le_bytes(bytes))
+}
+
+const fn shift_mix(val: u64) -> u64 {
+    val ^ (val >> 47)
+}
+
+const fn hash_len16(u: u64, v: u64) -> u64 {
+    let mut a = (u ^ v).wrapping_mul(K_MUL);
+    a ^= a >> 47;
+    let mut b = (v ^ a).wrapping_mul(K_MUL);
+    b ^= b >> 47;
+    b.wrapping_mul(K_MUL)
+}
+
+fn hash_len0to16(s: &[u8]) -> u64 {
+    let len = s.len() as u64;
+    if s.len() > 8 {
+        let a = fetch64(s, 0);
+        let b = fetch64(s, s.len() - 8);
+        return hash_len16(a, b.wrapping_add(len).rotate_right(len as u32)) ^ b;
+    }
+
+    if s.len() >= 4 {
+        let a = fetch32(s, 0);
+        return hash_len16(len.wrapping_add(a << 3), fetch32(s, s.len() - 4));
+    }
+
+    if let (Some(&a), Some(&c)) = (s.first(), s.last()) {
+        let b = s[s.len() >> 1];
+        let y = u32::from(a).wrapping_add(u32::from(b) << 8);
+        let z = (s.len() as u32).wrapping_add(u32::from(c) << 2);
+        return shift_mix(u64::from(y).wrapping_mul(K2) ^ u64::from(z).wrapping_mul(K3))
+            .wrapping_mul(K2);
+    }
+
+    K2
+}
+
+fn hash_len17to32(s: &[u8]) -> u64 {
+    let len = s.len();
+    let a = fetch64(s, 0).wrapping_mul(K1);
+    let b = fetch64(s, 8);
+    let c = fetch64(s, len - 8).wrapping_mul(K2);
+    let d = fetch64(s, len - 16).wrapping_mul(K0);
+    hash_len16(
+        a.wrapping_sub(b)
+            .rotate_right(43)
+            .wrapping_add(c.rotate_right(30))
+            .wrapping_add(d),
+        a.wrapping_add((b ^ K3).rotate_right(20))
+            .wrapping_sub(c)
+            .wrapping_add(len as u64)
+    )
+}
+
+fn hash_len33to64(s: &[u8]) -> u64 {
+    let len = s.len();
+    let mut z = fetch64(s, 24);
+    let mut a = fetch64(s, 0).wrapping_add(
+        (len as u64)
+            .wrapping_add(fetch64(s, len - 16))
+            .wrapping_mul(K0)
+    );
+    let mut b = a.wrapping_add(z).rotate_right(52);
+    let mut c = a.rotate_right(37);
+    a = a.wrapping_add(fetch64(s, 8));
+    c = c.wrapping_add(a.rotate_right(7));
+    a = a.wrapping_add(fetch64(s, 16));
+    let vf = a.wrapping_add(z);
+    let vs = b.wrapping_add(a.rotate_right(31)).wrapping_add(c);
+
+    a = fetch64(s, 16).wrapping_add(fetch64(s, len - 32));
+    z = fetch64(s, len - 8);
+    b = a.wrapping_add(z).rotate_right(52);
+    c = a.rotate_right(37);
+    a = a.wrapping_add(fetch64(s, len - 24));
+    c = c.wrapping_add(a.rotate_right(7));
+    a = a.wrapping_add(fetch64(s, len - 16));
+    let wf = a.wrapping_add(z);
+    let ws = b.wrapping_add(a.rotate_right(31)).wrapping_add(c);
+
+    let r = shift_mix(
+        vf.wrapping_add(ws)
+            .wrapping_mul(K2)
+            .wrapping_add(wf.wrapping_add(vs).wrapping_mul(K0))
+    );
+    shift_mix(r.wrapping_mul(K0).wrapping_add(vs)).wrapping_mul(K2)
+}
+
+fn weak_hash_len32_with_seeds(s: &[u8], i: usize, mut a: u64, mut b: u64) -> (u64, u64) {
+    let (w, x, y, z) = (
+        fetch64(s, i),
+        fetch64(s, i + 8),
+        fetch64(s, i + 16),
+        fetch64(s, i + 24)
+    );
+    a = a.wrapping_add(w);
+    b = b.wrapping_add(a).wrapping_add(z).rotate_right(21);
+    let c = a;
+    a = a.wrapping_add(x).wrapping_add(y);
+    b = b.wrapping_add(a.rotate_right(44));
+    (a.wrapping_add(z), b.wrapping_add(c))
+}
+
+fn city_hash64(s: &[u8]) -> u64 {
+    let len = s.len();
+    match len {
+        0..=16 => return hash_len0to16(s),
+        17..=32 => return hash_len17to32(s),
+        33..=64 => return hash_len33to64(s),
+        _ => {}
+    }
+
+    // The end is hashed first, then 64 byte chunks from the start
+    let mut x = fetch64(s, 0);
+    let mut y = fetch64(s, len - 16) ^ K1;
+    let mut z = fetch64(s, len - 56) ^ K0;
+    let mut v = weak_hash_len32_with_seeds(s, len - 64, len as u64, y);
+    let mut w = weak_hash_len32_with_seeds(s, len - 32, (len as u64).wrapping_mul(K1), K0);
+    z = z.wrapping_add(shift_mix(v.1).wrapping_mul(K1));
+    x = z.wrapping_add(x).rotate_right(39).wrapping_mul(K1);
+    y = y.rotate_right(33).wrapping_mul(K1);
+
+    for chunk in (0..(len - 1) & !63).step_by(64) {
+        x = x
+            .wrapping_add(y)
+            .wrapping_add(v.0)
+            .wrapping_add(fetch64(s, chunk + 16))
+            .rotate_right(37)
+            .wrapping_mul(K1);
+        y = y
+            .wrapping_add(v.1)
+            .wrapping_add(fetch64(s, chunk + 48))
+            .rotate_right(42)
+            .wrapping_mul(K1);
+        x ^= w.1;
+        y ^= v.0;
+        z = (z ^ w.0).rotate_right(33);
+        v = weak_hash_len32_with_seeds(s, chunk, v.1.wrapping_mul(K1), x.wrapping_add(w.0));
+        w = weak_hash_len32_with_seeds(s, chunk + 32, z.wrapping_add(w.1), y);
+        std::mem::swap(&mut z, &mut x);
+    }
+
+    hash_len16(
+        hash_len16(v.0, w.0)
+            .wrapping_add(shift_mix(y).wrapping_mul(K1))
+            .wrapping_add(z),
+        hash_len16(v.1, w.1).wrapping_add(x)
+    )
+}
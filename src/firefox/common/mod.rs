@@ -1,24 +1,54 @@
 use std::path::Path;
+use color_eyre::eyre::WrapErr;
 use crate::util::select_profiles;
 use tracing::{debug, debug_span, warn};
 use crate::browser::Browser;
+use crate::browser::installation::Installation;
 use crate::browser::profile::BrowserProfile;
+use crate::FirefoxMode;
+use prefs::Pref;
 use crate::util::args;
+use crate::util::content_settings;
+use crate::util::hardening;
+use crate::util::lock::{LockKind, ensure_unlocked};
+use crate::util::transaction::Transaction;
 
+pub mod app_defaults;
 mod backup;
-mod profiles;
+pub mod channel;
+pub mod clean_prefs;
+pub mod css;
+pub mod defaults;
+mod install_hash;
+pub mod prefs;
+mod prefs_js;
+pub mod profiles;
 mod user_js;
 
+pub use user_js::configure_user_js;
+
+// Returns the profiles that were debloated. `profile_prefs` are prefs that depend on what's in the
+// profile, they're written into prefs.js once so the user can still change them.
 pub fn debloat<B: Browser>(
     data_folder: &Path,
+    installation: &Installation,
     user_js: &str,
-    additional_snippets: &str
+    profile_prefs: &dyn Fn(&BrowserProfile) -> color_eyre::Result<Vec<Pref>>,
+    transaction: &mut Transaction
 ) -> color_eyre::Result<Vec<BrowserProfile>> {
-    let (defaults, profiles) = profiles::get_profiles(data_folder)?;
+    // Installs of other channels share the data folder, their app folders tell them apart
+    let app_folders =
+        B::installations().into_iter().flat_map(|i| i.app_folders).collect::<Vec<_>>();
+    let profiles = profiles::get_profiles(data_folder, installation.variant, &app_folders);
+    let (defaults, profiles) = match profiles {
+        Ok(profiles) => profiles,
+        Err(why) => {
+            warn!(err = ?why, "Failed to read profiles in {}", data_folder.display());
+            return Ok(Vec::new());
+        }
+    };
     debug!("Found {} valid profiles", profiles.len());
 
-    return Ok(profiles);
-
     if profiles.is_empty() {
         return Ok(Vec::new());
     }
@@ -29,24 +59,50 @@ pub fn debloat<B: Browser>(
         return Ok(Vec::new());
     }
 
-    for profile in &profiles {
+    let mut debloated = Vec::with_capacity(profiles.len());
+    for profile in profiles {
         let span = debug_span!("Debloating profile", profile = %profile);
         let _enter = span.enter();
 
-        if args().backup
-            && let Err(why) = backup::backup_profile(profile)
-        {
-            warn!(err = ?why, "Failed to backup profile {profile}");
+        if !ensure_unlocked(LockKind::Firefox, &profile.path, &profile) {
             continue;
         }
 
-        if let Err(why) = user_js::install_user_js(profile, user_js, additional_snippets) {
-            warn!(err = ?why, "Failed to install user.js for profile {profile}");
-            continue;
+        if args().backup {
+            backup::backup_profile(installation, &profile)
+                .wrap_err_with(|| format!("Failed to backup profile {profile}"))?;
         }
 
+        let extra = profile_prefs(&profile)
+            .wrap_err_with(|| format!("Failed to build prefs for profile {profile}"))?;
+
+        match args().firefox_mode {
+            FirefoxMode::Userjs => {
+                user_js::install_user_js(&profile, user_js, transaction)
+                    .wrap_err_with(|| format!("Failed to install user.js for profile {profile}"))?;
+                if !extra.is_empty() {
+                    prefs_js::write_prefs(&profile, &extra, transaction).wrap_err_with(|| {
+                        format!("Failed to write prefs.js for profile {profile}")
+                    })?;
+                }
+            }
+            FirefoxMode::Prefsjs => {
+                prefs_js::install_prefs_js(&profile, user_js, &extra, transaction)
+                    .wrap_err_with(|| format!("Failed to write prefs.js for profile {profile}"))?;
+            }
+        }
+
+        css::install(&profile, transaction)
+            .wrap_err_with(|| format!("Failed to install CSS for profile {profile}"))?;
+
+        content_settings::firefox_site_permissions(&profile.path, transaction)
+            .wrap_err_with(|| format!("Failed to set site permissions for profile {profile}"))?;
+        hardening::firefox_kept_cookies(&profile.path, transaction)
+            .wrap_err_with(|| format!("Failed to keep cookies for profile {profile}"))?;
+
         debug!("Finished debloating profile");
+        debloated.push(profile);
     }
 
-    Ok(profiles)
+    Ok(debloated)
 }
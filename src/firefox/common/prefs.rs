@@ -0,0 +1,133 @@
+use std::fmt::Display;
+
+// Every function a prefs file can set a pref with, from user.js up to autoconfig
+const PREF_FUNCTIONS: [&str; 5] = ["user_pref", "pref", "defaultPref", "lockPref", "sticky_pref"];
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PrefValue {
+    Bool(bool),
+    Int(i64),
+    String(String)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pref {
+    pub name: String,
+    pub value: PrefValue
+}
+
+// Formats as a JS literal, which is also what distribution.ini expects
+impl Display for PrefValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Int(i) => write!(f, "{i}"),
+            Self::String(s) => write!(f, "{}", serde_json::Value::String(s.clone()))
+        }
+    }
+}
+
+impl Pref {
+    // The line Firefox writes into prefs.js for this pref
+    pub fn to_user_pref(&self) -> String {
+        let value = match &self.value {
+            PrefValue::String(s) => format!("\"{}\"", escape(s)),
+            value => value.to_string()
+        };
+
+        format!("user_pref(\"{}\", {value});", escape(&self.name))
+    }
+}
+
+// Only quotes, backslashes and line breaks are escaped in prefs.js, everything else is kept as is
+fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            c => ret.push(c)
+        }
+    }
+
+    ret
+}
+
+// Parses every uncommented pref call, later calls for the same pref win like they do in Firefox
+pub fn parse_prefs(js: &str) -> Vec<Pref> {
+    let mut prefs = Vec::<Pref>::new();
+    let mut in_block_comment = false;
+
+    for line in js.lines() {
+        let line = line.trim();
+        if in_block_comment {
+            in_block_comment = !line.contains("*/");
+            continue;
+        }
+
+        if line.starts_with("/*") {
+            in_block_comment = !line.contains("*/");
+            continue;
+        }
+
+        let Some(pref) = parse_line(line) else {
+            continue;
+        };
+
+        if let Some(existing) = prefs.iter_mut().find(|p| p.name == pref.name) {
+            existing.value = pref.value;
+        } else {
+            prefs.push(pref);
+        }
+    }
+
+    prefs
+}
+
+fn parse_line(line: &str) -> Option<Pref> {
+    let (function, rest) = line.split_once('(')?;
+    if !PREF_FUNCTIONS.contains(&function.trim()) {
+        return None;
+    }
+
+    let (name, rest) = parse_string(rest.trim_start())?;
+    let rest = rest.trim_start().strip_prefix(',')?.trim_start();
+
+    let value = if rest.starts_with(['"', '\'']) {
+        let (value, rest) = parse_string(rest)?;
+        rest.trim_start().starts_with(')').then_some(PrefValue::String(value))?
+    } else {
+        let (value, _) = rest.split_once(')')?;
+        match value.trim() {
+            "true" => PrefValue::Bool(true),
+            "false" => PrefValue::Bool(false),
+            value => PrefValue::Int(value.parse().ok()?)
+        }
+    };
+
+    Some(Pref { name, value })
+}
+
+// Returns the unescaped contents of a leading string literal and whatever follows it
+fn parse_string(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.char_indices();
+    let (_, quote) = chars.next().filter(|(_, c)| matches!(c, '"' | '\''))?;
+
+    let mut ret = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next()?.1 {
+                'n' => ret.push('\n'),
+                'r' => ret.push('\r'),
+                't' => ret.push('\t'),
+                escaped => ret.push(escaped)
+            },
+            c if c == quote => return Some((ret, s.get(i + 1..)?)),
+            c => ret.push(c)
+        }
+    }
+
+    None
+}
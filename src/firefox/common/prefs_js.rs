@@ -0,0 +1,112 @@
+use crate::{
+    browser::profile::BrowserProfile, firefox::common::{prefs::{Pref, parse_prefs}, user_js}, util::{UnwrapOrExit, args, transaction::Transaction}
+};
+use color_eyre::eyre::WrapErr;
+use std::{collections::BTreeMap, fs, path::Path};
+use tracing::{debug, warn};
+
+// Exactly what Firefox starts prefs.js with
+const HEADER: &str = "// Mozilla User Preferences
+
+// DO NOT EDIT THIS FILE.
+//
+// If you make changes to this file while the application is running,
+// the changes will be overwritten when the application exits.
+//
+// To change a preference value, you can either:
+// - modify it via the UI (e.g. via about:config in the browser); or
+// - set it within a user.js file in your profile.
+
+";
+
+// Writes the prefs of `user_js` into prefs.js once, so they can still be changed from settings,
+// and takes out the user.js an earlier run installed since it would reset them on every start
+pub fn install_prefs_js(
+    profile: &BrowserProfile,
+    user_js: &str,
+    extra: &[Pref],
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
+    let mut prefs = parse_prefs(user_js);
+    prefs.extend_from_slice(extra);
+    write_prefs(profile, &prefs, transaction)?;
+
+    remove_our_user_js(profile, &profile.path.join("user.js"), transaction)
+}
+
+// Sets `prefs` in prefs.js, keeping everything else in it
+pub fn write_prefs(
+    profile: &BrowserProfile,
+    prefs: &[Pref],
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
+    let prefs_path = profile.path.join("prefs.js");
+    let existing = match fs::read_to_string(&prefs_path) {
+        Ok(existing) => existing,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(why) => return Err(why).wrap_err("Failed to read prefs.js")
+    };
+
+    transaction
+        .write(&prefs_path, merge(&existing, prefs))
+        .wrap_err("Failed to write prefs.js")?;
+    debug!("Wrote {} prefs into prefs.js", prefs.len());
+    Ok(())
+}
+
+// Firefox keeps prefs.js sorted by name, with one user_pref call per line
+fn merge(existing: &str, prefs: &[Pref]) -> String {
+    let mut lines = BTreeMap::new();
+    let mut unparsed = Vec::new();
+    for line in existing.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with("//")) {
+        match parse_prefs(line).pop() {
+            Some(pref) => {
+                lines.insert(pref.name, line.to_owned());
+            }
+            // Never drop something Firefox wrote just because we can't read it
+            None => unparsed.push(line)
+        }
+    }
+
+    for pref in prefs {
+        lines.insert(pref.name.clone(), pref.to_user_pref());
+    }
+
+    let mut prefs_js = String::from(HEADER);
+    for line in lines.values().map(String::as_str).chain(unparsed) {
+        prefs_js.push_str(line);
+        prefs_js.push('\n');
+    }
+
+    prefs_js
+}
+
+fn remove_our_user_js(
+    profile: &BrowserProfile,
+    path: &Path,
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
+    let Ok(user_js) = fs::read_to_string(path) else {
+        return Ok(());
+    };
+
+    if user_js::is_ours(&user_js) {
+        debug!("Removing the user.js of an earlier run");
+        return transaction.remove(path).wrap_err("Failed to remove user.js");
+    }
+
+    // Could be the user's own, or one from a clenzy version that didn't mark it
+    let remove = !args().auto_confirm
+        && inquire::Confirm::new(&format!(
+            "{profile} has a user.js clenzy didn't write, its prefs override prefs.js. Remove it? \
+             (y/n)"
+        ))
+        .prompt()
+        .unwrap_or_exit();
+    if remove {
+        return transaction.remove(path).wrap_err("Failed to remove user.js");
+    }
+
+    warn!("{profile} has a user.js clenzy didn't write, its prefs still override prefs.js");
+    Ok(())
+}
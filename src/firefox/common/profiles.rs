@@ -1,52 +1,232 @@
-use crate::util::validate_profile_dir;
+use crate::{
+    browser::{installation::Variant, profile::BrowserProfile}, firefox::common::{channel::Channel, install_hash::install_hash}, util::validate_profile_dir
+};
 use color_eyre::eyre::WrapErr;
-use ini::Ini;
-use std::{fs, path::Path};
-use tracing::debug;
-use crate::browser::profile::BrowserProfile;
-
-// Returns the number of default profiles and a vector of all profiles
-pub fn get_profiles(path: &Path) -> color_eyre::Result<(usize, Vec<BrowserProfile>)> {
-    let profiles_str =
-        fs::read_to_string(path.join("profiles.ini")).wrap_err("Failed to read profiles.ini")?;
-    let profiles_doc =
-        Ini::load_from_str(&profiles_str).wrap_err("Failed to parse profiles.ini")?;
-    drop(profiles_str);
-
-    debug!(len = %profiles_doc.len(), "Profiles ini read");
-
-    let mut profiles = profiles_doc
+use ini::{Ini, Properties};
+use rusqlite::{Connection, OpenFlags};
+use std::{
+    collections::HashSet, fs, path::{Path, PathBuf}
+};
+use tracing::{debug, warn};
+
+#[derive(Clone, Debug)]
+pub struct ProfileEntry {
+    pub name: String,
+    pub path: PathBuf,
+    // Legacy `Default=1`, only used when no install claims a default profile
+    pub is_default: bool,
+    // Profiles created through the new profile selector share a group store
+    pub store_id: Option<String>
+}
+
+#[derive(Clone, Debug)]
+pub struct InstallEntry {
+    // CityHash64 of the install directory, as written by Firefox
+    pub hash: String,
+    pub default: PathBuf,
+    // Locked installs never switch away from their default profile
+    pub locked: bool,
+    pub channel: Channel
+}
+
+#[derive(Debug, Default)]
+pub struct ProfilesIni {
+    pub profiles: Vec<ProfileEntry>,
+    pub installs: Vec<InstallEntry>
+}
+
+impl ProfilesIni {
+    // `app_folders` are every install directory found, to tell which install each section is for
+    pub fn load(root: &Path, app_folders: &[PathBuf]) -> color_eyre::Result<Self> {
+        let profiles_str =
+            fs::read_to_string(root.join("profiles.ini")).wrap_err("Failed to read profiles.ini")?;
+        let profiles_doc =
+            Ini::load_from_str(&profiles_str).wrap_err("Failed to parse profiles.ini")?;
+        drop(profiles_str);
+
+        debug!(len = %profiles_doc.len(), "Profiles ini read");
+
+        let mut seen = HashSet::new();
+        let mut profiles = Vec::new();
+        // installs.ini is the older copy, the [Install*] sections in profiles.ini take precedence
+        let mut installs = read_installs_ini(root);
+
+        for (section, prop) in &profiles_doc {
+            let Some(section) = section else {
+                continue;
+            };
+
+            if let Some(hash) = section.strip_prefix("Install") {
+                if let Some(install) = parse_install(root, hash, prop) {
+                    installs.retain(|i| i.hash != install.hash);
+                    installs.push(install);
+                }
+            } else if section.starts_with("Profile")
+                && let Some(profile) = parse_profile(root, prop)
+                && seen.insert(profile.path.clone())
+            {
+                profiles.push(profile);
+            }
+        }
+
+        for install in &mut installs {
+            if let Some(channel) = app_folders
+                .iter()
+                .find(|f| install_hash(f) == install.hash)
+                .and_then(|f| Channel::from_app_folder(f))
+            {
+                install.channel = channel;
+            }
+        }
+
+        let store_ids =
+            profiles.iter().filter_map(|p| p.store_id.clone()).collect::<HashSet<_>>();
+        for store_id in store_ids {
+            match read_profile_group(root, &store_id) {
+                Ok(group) => {
+                    debug!(%store_id, len = %group.len(), "Read profile group");
+                    profiles.extend(group.into_iter().filter(|p| seen.insert(p.path.clone())));
+                }
+                Err(why) => warn!(err = ?why, %store_id, "Failed to read profile group")
+            }
+        }
+
+        debug!(profiles = %profiles.len(), installs = %installs.len(), "Parsed profiles.ini");
+        Ok(Self { profiles, installs })
+    }
+
+    // Every variant that has a dedicated profile, plus stable if any profile isn't claimed
+    // by an install. Unclaimed profiles belong to the stable channel.
+    pub fn variants(&self) -> Vec<Option<Variant>> {
+        let mut variants = Vec::new();
+        for install in &self.installs {
+            let variant = install.channel.variant();
+            if !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+
+        if !variants.contains(&None) && self.profiles.iter().any(|p| !self.is_claimed(&p.path)) {
+            variants.push(None);
+        }
+
+        variants
+    }
+
+    fn is_claimed(&self, path: &Path) -> bool {
+        self.installs.iter().any(|i| i.default == path)
+    }
+
+    // Returns the number of default profiles and the profiles of the variant, defaults first
+    pub fn profiles_for(&self, variant: Option<Variant>) -> (usize, Vec<&ProfileEntry>) {
+        let installs = self
+            .installs
+            .iter()
+            .filter(|i| i.channel.variant() == variant)
+            .inspect(|i| {
+                debug!(hash = %i.hash, locked = %i.locked, default = %i.default.display(), "Install matches variant");
+            })
+            .collect::<Vec<_>>();
+
+        let has_dedicated = !installs.is_empty();
+        let (defaults, rest): (Vec<_>, Vec<_>) = self
+            .profiles
+            .iter()
+            .filter(|p| {
+                installs.iter().any(|i| i.default == p.path)
+                    || (variant.is_none() && !self.is_claimed(&p.path))
+            })
+            .partition(|p| {
+                installs.iter().any(|i| i.default == p.path) || (!has_dedicated && p.is_default)
+            });
+
+        (defaults.len(), [defaults, rest].concat())
+    }
+}
+
+fn resolve_path(root: &Path, path: &str, is_relative: bool) -> PathBuf {
+    if is_relative { root.join(path) } else { PathBuf::from(path) }
+}
+
+fn parse_profile(root: &Path, prop: &Properties) -> Option<ProfileEntry> {
+    let is_relative = prop.get("IsRelative").is_none_or(|r| r.trim() != "0");
+    Some(ProfileEntry {
+        name: prop.get("Name")?.to_owned(),
+        path: resolve_path(root, prop.get("Path")?, is_relative),
+        is_default: prop.get("Default").is_some_and(|d| d.trim() == "1"),
+        store_id: prop.get("StoreID").map(String::from)
+    })
+}
+
+// Install defaults are always relative to the root unless they're absolute. Until the install is
+// matched to an app folder, its channel is guessed from the name of its default profile.
+fn parse_install(root: &Path, hash: &str, prop: &Properties) -> Option<InstallEntry> {
+    let default = prop.get("Default")?;
+    let default = resolve_path(root, default, !Path::new(default).is_absolute());
+    Some(InstallEntry {
+        hash: hash.to_owned(),
+        channel: Channel::from_profile_path(&default).unwrap_or(Channel::Release),
+        default,
+        locked: prop.get("Locked").is_some_and(|l| l.trim() == "1")
+    })
+}
+
+fn read_installs_ini(root: &Path) -> Vec<InstallEntry> {
+    let Ok(installs_doc) = Ini::load_from_file(root.join("installs.ini")) else {
+        debug!("No installs.ini found");
+        return Vec::new();
+    };
+
+    installs_doc
         .iter()
-        .filter_map(|(_, prop)| {
-            Some((
-                prop.get("Name")?,
-                prop.get("Path")?,
-                prop.get("Default").and_then(|d| d.parse::<u8>().ok()).unwrap_or_default() == 1
-            ))
-        })
-        .collect::<Vec<_>>();
+        .filter_map(|(section, prop)| parse_install(root, section?, prop))
+        .collect()
+}
 
-    let before_dedup = profiles.len();
-    profiles.dedup_by(|(_, first_path, _), (_, second_path, _)| first_path == second_path);
-    debug!(before = %before_dedup, after = %profiles.len(), "Deduplicated profiles");
+// Profiles created through the profile selector are only listed in `Profile Groups/<StoreID>.sqlite`
+fn read_profile_group(root: &Path, store_id: &str) -> color_eyre::Result<Vec<ProfileEntry>> {
+    let db_path = root.join("Profile Groups").join(format!("{store_id}.sqlite"));
+    let conn = Connection::open_with_flags(
+        &db_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX
+    )
+    .wrap_err_with(|| format!("Failed to open {}", db_path.display()))?;
 
-    // Split into default and non-default profiles
-    let profiles: (Vec<_>, Vec<_>) =
-        profiles.into_iter().partition(|(_, _, is_default)| *is_default);
-    let defaults = profiles.0.len();
+    let mut stmt = conn.prepare("SELECT name, path FROM Profiles")?;
+    let rows =
+        stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
 
-    debug!(len = %defaults, "Default profiles");
+    let profiles = rows
+        .filter_map(Result::ok)
+        .map(|(name, path)| ProfileEntry {
+            name,
+            path: resolve_path(root, &path, !Path::new(&path).is_absolute()),
+            is_default: false,
+            store_id: Some(store_id.to_owned())
+        })
+        .collect();
 
-    // Make sure defaults are first
-    let profiles = <[_; 2]>::from(profiles)
-        .concat()
+    Ok(profiles)
+}
+
+// Returns the number of default profiles and a vector of all profiles for the variant
+pub fn get_profiles(
+    path: &Path,
+    variant: Option<Variant>,
+    app_folders: &[PathBuf]
+) -> color_eyre::Result<(usize, Vec<BrowserProfile>)> {
+    let profiles_ini = ProfilesIni::load(path, app_folders)?;
+    let (defaults, profiles) = profiles_ini.profiles_for(variant);
+
+    let mut valid_defaults = 0;
+    let profiles = profiles
         .into_iter()
-        .map(|(name, profile_path, _)| {
-            BrowserProfile::new(String::from(name), path.join(profile_path))
-        })
-        .filter(|profile| validate_profile_dir(&profile.path))
+        .enumerate()
+        .filter(|(_, profile)| validate_profile_dir(&profile.path))
+        .inspect(|(i, _)| valid_defaults += usize::from(*i < defaults))
+        .map(|(_, profile)| BrowserProfile::new(profile.name.clone(), profile.path.clone()))
         .collect::<Vec<_>>();
 
-    debug!("Found {} valid profiles", profiles.len() + defaults);
-    Ok((defaults, profiles))
+    debug!(defaults = %valid_defaults, "Found {} valid profiles", profiles.len());
+    Ok((valid_defaults, profiles))
 }
@@ -1,46 +1,71 @@
 use crate::{
-    browser::profile::BrowserProfile, util::{UnwrapOrExit, args}
+    browser::profile::BrowserProfile, firefox::common::prefs::parse_prefs, util::{UnwrapOrExit, args, transaction::Transaction}
 };
 use color_eyre::eyre::{ContextCompat, WrapErr};
 use std::{fs, path::Path};
 use tracing::debug;
 
-pub fn install_user_js(
-    profile: &BrowserProfile,
-    user_js: &str,
-    additional_snippets: &str
-) -> color_eyre::Result<()> {
-    let user_js_path = profile.path.join("user.js");
+// Where Betterfox leaves room for overrides
+const OVERRIDES_MARKER: &str = "* START: MY OVERRIDE";
+// First line of every user.js clenzy writes, Betterfox's own header can't tell ours apart from
+// one the user installed
+const CLENZY_HEADER: &str = "// Written by clenzy, changes are overwritten on the next run";
+// Every clenzy from before the header added these to Betterfox's overrides, Betterfox itself
+// sets none of them
+const LEGACY_PREFS: &str = r#"
+user_pref("browser.contentblocking.category", "standard");
+user_pref("browser.urlbar.scotchBonnet.enableOverride", false);
+user_pref("privacy.userContext.enabled", false);
+"#;
+
+// Whether clenzy wrote this user.js, also one an older version wrote without the header
+pub fn is_ours(user_js: &str) -> bool {
+    if user_js.lines().next().is_some_and(|l| l.trim() == CLENZY_HEADER) {
+        return true;
+    }
+
+    let prefs = parse_prefs(user_js);
+    user_js.contains(OVERRIDES_MARKER) && parse_prefs(LEGACY_PREFS).iter().all(|p| prefs.contains(p))
+}
+
+// Inserts our snippets into the "my overrides" section of the fetched user.js
+pub fn configure_user_js(user_js: &str, additional_snippets: &str) -> color_eyre::Result<String> {
+    let mut lines = vec![CLENZY_HEADER];
+    lines.extend(user_js.lines());
+    let start_my_overrides_pos = lines
+        .iter()
+        .rposition(|l| l.trim().starts_with(OVERRIDES_MARKER))
+        .wrap_err("Failed to find start of 'my overrides'")?;
 
-    let configured_user_js = {
-        let mut lines = user_js.lines().collect::<Vec<_>>();
-        let start_my_overrides_pos = lines
-            .iter()
-            .rposition(|l| l.trim().starts_with("* START: MY OVERRIDE"))
-            .wrap_err("Failed to find start of 'my overrides'")?;
+    // Skip comments and a blank space
+    let start_my_overrides_pos = start_my_overrides_pos + 6;
 
-        // Skip comments and a blank space
-        let start_my_overrides_pos = start_my_overrides_pos + 6;
+    if !additional_snippets.is_empty() {
+        lines.insert(start_my_overrides_pos, additional_snippets);
+    }
 
-        if !additional_snippets.is_empty() {
-            lines.insert(start_my_overrides_pos, additional_snippets);
-        }
+    debug!(
+        "Added {} additional lines to user.js (originally {})",
+        additional_snippets.lines().count(),
+        lines.len()
+    );
+    Ok(lines.join::<&str>("\n"))
+}
 
-        debug!(
-            "Added {} additional lines to user.js (originally {})",
-            additional_snippets.lines().count(),
-            lines.len()
-        );
-        Ok::<String, color_eyre::eyre::Error>(lines.join::<&str>("\n"))
-    }?;
+pub fn install_user_js(
+    profile: &BrowserProfile,
+    configured_user_js: &str,
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
+    let user_js_path = profile.path.join("user.js");
 
     // Checks if user.js exists and content differs from configured_user_js
-    if !should_write_user_js(profile, &user_js_path, &configured_user_js) {
+    if !should_write_user_js(profile, &user_js_path, configured_user_js) {
         debug!(path = %user_js_path.display(), "Not overwriting user.js");
         return Ok(());
     }
 
-    fs::write(&user_js_path, configured_user_js).wrap_err("Failed to write user.js")
+    transaction.write(&user_js_path, configured_user_js).wrap_err("Failed to write user.js")
 }
 
 fn should_write_user_js(profile: &BrowserProfile, path: &Path, user_js_str: &str) -> bool {
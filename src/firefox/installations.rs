@@ -1,7 +1,10 @@
-use crate::util::{flatpak_base, local_app_bases, local_snap_base, roaming_data_base};
+use crate::util::{flatpak_base, local_app_bases, local_snap_base, roaming_data_base, users};
 use std::path::PathBuf;
-use crate::browser::installation::{Installation, InstalledVia};
-use crate::firefox::Firefox;
+use tracing::debug;
+use crate::browser::installation::{Installation, InstalledVia, Variant};
+use crate::firefox::{
+    Firefox, common::{channel::Channel, profiles::ProfilesIni}
+};
 
 fn local() -> Vec<PathBuf> {
     let mut ret = Vec::with_capacity(3);
@@ -29,31 +32,85 @@ fn local() -> Vec<PathBuf> {
     ret
 }
 
-// We're not going to classify the variants as different installations, as there's
-// no easy way to differentiate the profiles.
 fn local_apps() -> Vec<PathBuf> {
     let bases = local_app_bases();
     let variants = ["Firefox", "Mozilla Firefox", "Firefox Developer Edition", "Firefox Nightly"];
 
     if cfg!(target_os = "windows") {
-        let mut bases = bases.collect::<Vec<_>>();
-        if let Some(local_app_data) = dirs::data_local_dir() {
-            bases.push(local_app_data);
-        }
-
-        bases.into_iter().flat_map(|f| variants.iter().map(move |v| f.join(v))).collect()
+        bases
+            .chain(dirs::data_local_dir())
+            .flat_map(|f| variants.iter().map(move |v| f.join(v)))
+            .collect()
     } else if cfg!(target_os = "macos") {
         bases
             .flat_map(|f| variants.iter().map(move |v| f.join(format!("{v}.app")).join("Contents")))
             .collect()
     } else {
-        let mut bases = bases.map(|f| f.join("firefox")).collect::<Vec<_>>();
-        if let Some(home) = dirs::home_dir() {
-            bases.push(home.join("firefox"));
+        let linux_variants = [
+            "firefox",
+            "firefox-esr",
+            "firefox-beta",
+            "firefox-developer-edition",
+            "firefox-nightly"
+        ];
+        let mut bases = bases
+            .chain([PathBuf::from("/usr/lib"), PathBuf::from("/usr/lib64")])
+            .chain(users::home_dir())
+            .collect::<Vec<_>>();
+        bases.dedup();
+
+        bases.into_iter().flat_map(|f| linux_variants.iter().map(move |v| f.join(v))).collect()
+    }
+}
+
+// One data folder is shared by every channel, so split it up by the installs in profiles.ini
+// and the update channel each app folder was built for.
+fn local_installations() -> Vec<Installation> {
+    let app_folders = local_apps().into_iter().filter(|f| f.exists()).collect::<Vec<_>>();
+    let mut grouped: Vec<(Option<Variant>, Vec<PathBuf>, Vec<PathBuf>)> = Vec::new();
+
+    let mut group =
+        |variant: Option<Variant>, data_folder: Option<PathBuf>, app_folder: Option<PathBuf>| {
+            let position =
+                grouped.iter().position(|(v, _, _)| *v == variant).unwrap_or_else(|| {
+                    grouped.push((variant, Vec::new(), Vec::new()));
+                    grouped.len() - 1
+                });
+
+            if let Some((_, data_folders, app_folders)) = grouped.get_mut(position) {
+                data_folders.extend(data_folder.filter(|f| !data_folders.contains(f)));
+                app_folders.extend(app_folder);
+            }
+        };
+
+    for data_folder in local().into_iter().filter(|f| f.exists()) {
+        let variants = ProfilesIni::load(&data_folder, &app_folders)
+            .map_or_else(|_| vec![None], |ini| ini.variants());
+
+        debug!(path = %data_folder.display(), ?variants, "Found Firefox variants");
+        for variant in variants {
+            group(variant, Some(data_folder.clone()), None);
         }
+    }
 
-        bases
+    for app_folder in app_folders {
+        let variant = Channel::from_app_folder(&app_folder).and_then(Channel::variant);
+        group(variant, None, Some(app_folder));
     }
+
+    grouped
+        .into_iter()
+        .map(|(variant, data_folders, app_folders)| {
+            let mut builder = Installation::builder::<Firefox>()
+                .data_folders(data_folders)
+                .app_folders(app_folders);
+            if let Some(variant) = variant {
+                builder = builder.variant(variant);
+            }
+
+            builder.build()
+        })
+        .collect()
 }
 
 fn snap() -> Option<PathBuf> {
@@ -73,14 +130,10 @@ fn flatpak_app() -> PathBuf {
 }
 
 pub fn installations() -> Vec<Installation> {
-    let mut ret = Vec::with_capacity(3);
-
-    ret.push(
-        Installation::builder::<Firefox>()
-            .data_folders(local())
-            .app_folders(local_apps())
-            .build()
-    );
+    let mut ret = local_installations();
+    if ret.is_empty() {
+        ret.push(Installation::builder::<Firefox>().build());
+    }
 
     if cfg!(target_os = "linux") {
         ret.push(
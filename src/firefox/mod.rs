@@ -1,18 +1,26 @@
 mod installations;
 mod policies;
+mod toolbar;
 pub mod resource;
 mod xulstore;
 pub mod common;
 
 #[cfg(target_os = "linux")]
-pub use policies::create_linux_policies_file;
+pub use policies::{create_linux_policies_file, verify_linux_policies};
+pub use policies::{
+    installed_policy_files, linux_policies_folder, read_policies, validated_policies
+};
 use crate::browser::Browser;
 use installations::installations;
 use std::path::Path;
+use color_eyre::eyre::WrapErr;
 use tracing::{debug, debug_span, warn};
 use crate::browser::installation::Installation;
 use crate::browser::profile::BrowserProfile;
 use crate::util::args;
+use crate::util::content_settings;
+use crate::util::hardening;
+use crate::util::transaction::Transaction;
 
 pub struct Firefox;
 
@@ -29,55 +37,78 @@ impl Browser for Firefox {
         Some(resource::get_better_fox_user_js)
     }
 
-    fn debloat(installation: &Installation) -> color_eyre::Result<()> {
-        debloat(installation);
-        Ok(())
+    fn debloat(
+        installation: &Installation,
+        transaction: &mut Transaction
+    ) -> color_eyre::Result<()> {
+        debloat(installation, transaction)
     }
 }
 
-#[allow(clippy::unnecessary_wraps)]
-pub fn debloat(installation: &Installation) {
+// Betterfox with our overrides, as it gets written to every profile
+pub fn configured_user_js() -> color_eyre::Result<String> {
+    user_js_with(args().vertical_tabs, args().search_suggestions)
+}
+
+// The user.js for one set of options, export writes one for each
+pub fn user_js_with(vertical_tabs: bool, search_suggestions: bool) -> color_eyre::Result<String> {
+    let permissions = content_settings::firefox_user_js();
     let mut custom_overrides = vec![
         include_str!("../../snippets/firefox_common/betterfox_extra.js"),
-        include_str!("../../snippets/firefox/extra.js"),
+        &permissions,
     ];
+    custom_overrides.extend(hardening::firefox_snippets());
+    custom_overrides.push(include_str!("../../snippets/firefox/extra.js"));
 
-    if args().vertical_tabs {
+    if vertical_tabs {
         custom_overrides.push(include_str!("../../snippets/firefox/vert_tabs.js"));
     }
 
-    if args().search_suggestions {
+    if common::css::is_enabled() {
+        custom_overrides.push(include_str!("../../snippets/firefox_common/stylesheets.js"));
+    }
+
+    if search_suggestions {
         custom_overrides.push(include_str!("../../snippets/firefox_common/search_suggestions.js"));
     }
 
+    common::configure_user_js(resource::get_better_fox_user_js()?, &custom_overrides.join("\n"))
+}
+
+pub fn debloat(
+    installation: &Installation,
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
+    let user_js = configured_user_js()?;
+
     let mut found_profile = false;
     for data_folder in &installation.data_folders {
-        match debloat_profile(installation, data_folder, &custom_overrides[..]) {
-            Err(why) => {
-                warn!(err = ?why, "Failed to debloat data folder: {}", data_folder.display());
-            }
-            Ok(profiles) => {
-                found_profile |= !profiles.is_empty();
-                debug!(data_folder = %data_folder.display(), "Successfully debloated data folder");
-            }
-        }
+        let profiles = debloat_profile(installation, data_folder, &user_js, transaction)
+            .wrap_err_with(|| {
+                format!("Failed to debloat data folder: {}", data_folder.display())
+            })?;
+
+        found_profile |= !profiles.is_empty();
+        debug!(data_folder = %data_folder.display(), "Successfully debloated data folder");
     }
 
     if !found_profile {
         warn!("Failed to find any valid profiles in Firefox installation");
     }
+
+    common::app_defaults::install(installation, &user_js)
+        .wrap_err("Failed to install app defaults")
 }
 
 fn debloat_profile(
     installation: &Installation,
     data_folder: &Path,
-    custom_overrides: &[&str]
+    user_js: &str,
+    transaction: &mut Transaction
 ) -> color_eyre::Result<Vec<BrowserProfile>> {
-    let profiles = common::debloat::<Firefox>(
-        data_folder,
-        resource::get_better_fox_user_js()?,
-        &custom_overrides.join("\n")
-    )?;
+    let toolbar = |profile: &BrowserProfile| toolbar::customization_prefs(profile);
+    let profiles =
+        common::debloat::<Firefox>(data_folder, installation, user_js, &toolbar, transaction)?;
 
     if !args().vertical_tabs {
         return Ok(profiles);
@@ -87,25 +118,11 @@ fn debloat_profile(
         let span = debug_span!("Updating xulstore", %profile);
         let _enter = span.enter();
 
-        match xulstore::xulstore(&profile.path) {
-            Ok(()) => debug!("Updated xulstore.json for {profile}"),
-            Err(why) => warn!(err = %why, "Failed to update xulstore.json for {profile}")
-        }
+        xulstore::xulstore(&profile.path, transaction)
+            .wrap_err_with(|| format!("Failed to update xulstore.json for {profile}"))?;
+        debug!("Updated xulstore.json for {profile}");
     }
 
-    if !args().policies {
-    // }
-    //
-    // for profile in &profiles {
-    //     let span = debug_span!("Updating xulstore", %profile);
-    //     let _enter = span.enter();
-    //
-    //     match xulstore::xulstore(&profile.path) {
-    //         Ok(()) => debug!("Updated xulstore.json for {profile}"),
-    //         Err(why) => warn!(err = ?why, "Failed to update xulstore.json for {profile}")
-    //     }
-    // }
-
     if !args().policies || cfg!(target_os = "linux") {
         return Ok(profiles);
     }
@@ -116,7 +133,7 @@ fn debloat_profile(
     }
 
     for folder in &installation.app_folders {
-        if let Err(why) = policies::create_policies_file(folder) {
+        if let Err(why) = policies::create_policies_file(installation, folder) {
             warn!(err = ?why, "Failed to create policies file in {}", folder.display());
         }
     }
@@ -1,15 +1,23 @@
 use crate::{
-    s, util::{UnwrapOrExit, args, should_elevate}
+    Enforcement, browser::{Browser, installation::Installation}, firefox::{Firefox, common::prefs::{PrefValue, parse_prefs}, configured_user_js}, s, util::{args, backups::Slot, content_settings, elevate::{self, PrivilegedWrite}, hardening, policy_merge, policy_schema}, zen::Zen
 };
-use color_eyre::eyre::Context;
-use serde_json::json;
+use color_eyre::eyre::{Context, bail};
+use serde_json::{Map, Value, json};
 use std::{
     fs, path::{Path, PathBuf}
 };
+use crate::browser::installation::InstalledVia;
+#[cfg(target_os = "linux")]
+use tracing::warn;
 use tracing::debug;
 
-pub fn create_policies_file(installation_folder: &Path) -> color_eyre::Result<()> {
-    let policies = generate_policies()?;
+// Lives next to policies.json, Firefox only reads the `policies` key of that file anyway
+const OWNED_FILE: &str = "clenzy-owned-policies.json";
+
+pub fn create_policies_file(
+    installation: &Installation,
+    installation_folder: &Path
+) -> color_eyre::Result<()> {
     let folder = if cfg!(target_os = "macos") {
         // Firefox.app/Contents/Resources/distribution
         installation_folder.join("Firefox.app/Contents/Resources/distribution")
@@ -19,70 +27,194 @@ pub fn create_policies_file(installation_folder: &Path) -> color_eyre::Result<()
         return Ok(());
     };
 
+    let _ = fs::create_dir_all(&folder);
+    let folder = fs::canonicalize(&folder).unwrap_or(folder);
+    let backup = args().backup.then(|| Slot::policies(installation));
+    elevate::write_all(&merged_policies(&folder, backup.as_ref())?)
+        .wrap_err_with(|| format!("Failed to write policies to {}", folder.display()))
+}
+
+// Where each packaging of Firefox reads system policies from
+pub fn linux_policies_folder(installed_via: InstalledVia) -> PathBuf {
+    match installed_via {
+        // The snap is allowed to read this one folder of the host's /etc
+        InstalledVia::Local | InstalledVia::Snap => PathBuf::from("/etc/firefox/policies"),
+        // Flatpak can't see the host's /etc, this extension gets mounted at /app/etc/firefox
+        InstalledVia::Flatpak => PathBuf::from(format!(
+            "/var/lib/flatpak/extension/org.mozilla.firefox.systemconfig/{}/stable/policies",
+            std::env::consts::ARCH
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn create_linux_policies_file(
+    installations: &[&Installation],
+    backup: bool
+) -> color_eyre::Result<()> {
+    let mut folders = installations
+        .iter()
+        .filter(|i| i.browser_name == Firefox::name())
+        .map(|i| (linux_policies_folder(i.installed_via), i.installed_via))
+        .collect::<Vec<_>>();
+    folders.sort_by(|a, b| a.0.cmp(&b.0));
+    folders.dedup_by(|a, b| a.0 == b.0);
+
+    let mut files = Vec::new();
+    for (folder, installed_via) in &folders {
+        let slot = backup.then(|| Slot::system(Firefox::name(), *installed_via, None));
+        files.extend(merged_policies(folder, slot.as_ref())?);
+    }
+
+    // Older versions wrote to /etc/firefox/policies.json, which Firefox never reads
+    match released_policies(Path::new("/etc/firefox")) {
+        Ok(released) => files.extend(released),
+        Err(why) => {
+            warn!(err = ?why, "Failed to remove clenzy's policies from /etc/firefox/policies.json");
+        }
+    }
+
+    elevate::write_or_elevate("Policy creation", &files)
+}
+
+// Removes every policy we own from a policies.json we no longer write to
+#[cfg(target_os = "linux")]
+fn released_policies(folder: &Path) -> color_eyre::Result<Vec<PrivilegedWrite>> {
     let policies_path = folder.join("policies.json");
-    if !should_write_policies(&policies_path, &policies) {
-        debug!(path = %policies_path.display(), "Not overwriting policies.json");
-        return Ok(());
+    let owned_path = folder.join(OWNED_FILE);
+
+    let owned = policy_merge::read_owned(&owned_path)?;
+    if owned.is_empty() {
+        return Ok(Vec::new());
     }
 
-    let _ = fs::create_dir_all(fs::canonicalize(&folder).unwrap_or(folder));
-    fs::write(&policies_path, policies)
-        .wrap_err_with(|| format!("Failed to write policies to {}", &policies_path.display()))
+    let mut file = read_policies_file(&policies_path)?;
+    let existing = file.get("policies").and_then(Value::as_object).cloned().unwrap_or_default();
+    let released = policy_merge::merge(&existing, &Map::new(), &owned);
+    released.report("Firefox policies");
+    file.insert(s!("policies"), Value::Object(released.policies));
+
+    Ok(vec![
+        PrivilegedWrite::new(policies_path, serde_json::to_string_pretty(&file)?),
+        PrivilegedWrite::new(owned_path, policy_merge::serialize_owned(&released.owned)?),
+    ])
 }
 
+// Returns the policies.json this installation will read, if it has all of ours
 #[cfg(target_os = "linux")]
-pub fn create_linux_policies_file(backup: bool, short_circuit: bool) -> color_eyre::Result<()> {
-    let policies = generate_policies()?;
-    let policies_root = PathBuf::from("/etc/firefox");
+pub fn verify_linux_policies(installation: &Installation) -> color_eyre::Result<PathBuf> {
+    let path = linux_policies_folder(installation.installed_via).join("policies.json");
+    if !path.exists() {
+        bail!("{} doesn't exist", path.display());
+    }
 
-    if let Err(why) = fs::create_dir_all(&policies_root) {
-        if short_circuit {
-            return Err(why)
-                .wrap_err("Failed to create policies dir even with elevated permissions");
-        }
+    let policies = read_policies(&path)?;
+    let missing = generate_policies()?.keys().filter(|k| !policies.contains_key(*k)).count();
+    if missing > 0 {
+        bail!("{} is missing {missing} of clenzy's policies", path.display());
+    }
 
-        if !should_elevate() {
-            return Ok(());
-        }
+    Ok(path)
+}
+
+// Merges ours into whatever policies.json is already deployed instead of replacing it, returns
+// the files to write, if any
+fn merged_policies(
+    folder: &Path,
+    backup: Option<&Slot>
+) -> color_eyre::Result<Vec<PrivilegedWrite>> {
+    let policies_path = folder.join("policies.json");
+    let owned_path = folder.join(OWNED_FILE);
+
+    let mut file = read_policies_file(&policies_path)?;
+    let existing = file.get("policies").and_then(Value::as_object).cloned().unwrap_or_default();
+    let owned = policy_merge::read_owned(&owned_path)?;
 
-        debug!(err = ?why, "Failed to create policies directory, trying with elevated permissions");
-        return crate::util::elevate_and_run("--linux-firefox-policies");
+    let ours = validated_policies()?;
+
+    let merged = policy_merge::merge(&existing, &ours, &owned);
+    merged.report("Firefox policies");
+
+    if merged.policies == existing && merged.owned == owned {
+        debug!(path = %policies_path.display(), "policies.json is already up to date");
+        return Ok(Vec::new());
     }
 
-    if backup && !should_write_policies(&policies_root, &policies) {
-        return Ok(());
+    // policies.json is world readable, so the backup doesn't need elevated permissions
+    if let Some(slot) = backup
+        && policies_path.exists()
+    {
+        let original = fs::read(&policies_path).wrap_err("Failed to backup policies.json")?;
+        let backup_path = slot.save("policies.json", original, Some(&policies_path))?;
+        debug!("Backed up policies.json to {}", backup_path.display());
     }
 
-    let policies_path = policies_root.join("policies.json");
-    fs::write(&policies_path, policies)
-        .wrap_err_with(|| format!("Failed to write policies to {}", &policies_path.display()))
+    file.insert(s!("policies"), Value::Object(merged.policies));
+    Ok(vec![
+        PrivilegedWrite::new(policies_path, serde_json::to_string_pretty(&file)?),
+        PrivilegedWrite::new(owned_path, policy_merge::serialize_owned(&merged.owned)?),
+    ])
 }
 
-fn should_write_policies(policies_path: &Path, policies: &str) -> bool {
-    if !policies_path.exists() || args().auto_confirm {
-        return true;
+// Every policies.json Firefox or Zen would read for these installations
+pub fn installed_policy_files(installations: &[&Installation]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    #[cfg(target_os = "linux")]
+    for installation in installations.iter().filter(|i| i.browser_name == Firefox::name()) {
+        files.push(linux_policies_folder(installation.installed_via).join("policies.json"));
     }
 
-    let existing_fs_policies = fs::read_to_string(policies_path).unwrap_or_default();
-    if existing_fs_policies == policies {
-        debug!(path = %policies_path.display(), "policies.json already exists and is the same");
-        return false;
+    let browsers = [Firefox::name(), Zen::name()];
+    for installation in installations.iter().filter(|i| browsers.contains(&i.browser_name)) {
+        for folder in &installation.app_folders {
+            let root =
+                if cfg!(target_os = "macos") { folder.join("Resources") } else { folder.clone() };
+            files.push(root.join("distribution/policies.json"));
+        }
     }
 
-    if policies.is_empty() {
-        debug!(path = %policies_path.display(), "policies.json is empty");
-        return true;
+    files.sort();
+    files.dedup();
+    files.retain(|f| f.exists());
+    files
+}
+
+pub fn read_policies(path: &Path) -> color_eyre::Result<Map<String, Value>> {
+    let file = read_policies_file(path)?;
+    match file.get("policies") {
+        Some(Value::Object(policies)) => Ok(policies.clone()),
+        Some(_) => bail!("`policies` in {} isn't an object", path.display()),
+        None => bail!("{} has no `policies` key", path.display())
     }
+}
+
+fn read_policies_file(path: &Path) -> color_eyre::Result<Map<String, Value>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(Map::new()),
+        Err(why) => return Err(why).wrap_err_with(|| format!("Failed to read {}", path.display()))
+    };
 
-    inquire::Confirm::new(
-        "policies.json already exists for Firefox. Do you want to overwrite it? (y/n)"
-    )
-    .prompt()
-    .unwrap_or_exit()
+    // Never clobber a file we can't understand
+    let Value::Object(file) = serde_json::from_str::<Value>(&contents)
+        .wrap_err_with(|| format!("Failed to parse {}", path.display()))?
+    else {
+        bail!("{} isn't a JSON object", path.display());
+    };
+
+    Ok(file)
 }
 
-fn generate_policies() -> serde_json::Result<String> {
-    let mut policies = serde_json::Map::<String, serde_json::Value>::new();
+// Our policies, as they go under the `policies` key of a policies.json
+pub fn validated_policies() -> color_eyre::Result<Map<String, Value>> {
+    let policies = generate_policies()?;
+    policy_schema::ensure_valid("Firefox policies", &policy_schema::validate_firefox(&policies))?;
+    Ok(policies)
+}
+
+fn generate_policies() -> color_eyre::Result<Map<String, Value>> {
+    let locked = args().enforcement == Enforcement::Locked;
+    let mut policies = Map::new();
     // If set to false, application updates are downloaded but the user can choose when to install the update.
     policies.insert(s!("AppAutoUpdate"), json!(false));
     // Enables or disables autofill for payment methods.
@@ -121,7 +253,7 @@ fn generate_policies() -> serde_json::Result<String> {
         "Pocket": false,
         "SponsoredPocket": false,
         "Snippets": false,
-        "Locked": false
+        "Locked": locked
     });
     policies.insert(s!("FirefoxHome"), firefox_home);
     // Customize Firefox Suggest (US only).
@@ -129,7 +261,7 @@ fn generate_policies() -> serde_json::Result<String> {
         "WebSuggestions": args().search_suggestions,
         "SponsoredSuggestions": false,
         "ImproveSuggest": false,
-        "Locked": false
+        "Locked": locked
     });
     policies.insert(s!("FirefoxSuggest"), firefox_suggest);
     // Enable or disable network prediction (DNS prefetching).
@@ -153,9 +285,69 @@ fn generate_policies() -> serde_json::Result<String> {
         "SkipOnboarding": true, // If true, don’t show onboarding messages on the new tab page.
         "MoreFromMozilla": false, // If false, don’t show the "More from Mozilla" section in Preferences.
         "FirefoxLabs": false, // If false, don’t show the "Firefox Labs" section in Preferences.
-        "Locked": false // prevents the user from changing user messaging preferences
+        "Locked": locked // prevents the user from changing user messaging preferences
     });
     policies.insert(s!("UserMessaging"), user_messaging);
+    // Default permissions and site exceptions, only the ones passed on the command line.
+    policies.extend(content_settings::firefox_policies(locked));
+    // Tracking protection, HTTPS-only and clearing on shutdown for --hardening.
+    policies.extend(hardening::firefox_policies(locked));
+
+    let status = match args().enforcement {
+        Enforcement::Recommended => "default",
+        Enforcement::Managed => return Ok(policies),
+        Enforcement::Locked => "locked"
+    };
+    // Set our prefs as defaults or pin them, on top of what user.js does per profile
+    policies.insert(s!("Preferences"), preferences_policy(&configured_user_js()?, status));
+
+    Ok(policies)
+}
+
+fn preferences_policy(user_js: &str, status: &str) -> Value {
+    let preferences = parse_prefs(user_js)
+        .into_iter()
+        .filter(|pref| is_policy_pref(&pref.name))
+        .map(|pref| {
+            let value = match pref.value {
+                PrefValue::Bool(b) => json!(b),
+                PrefValue::Int(i) => json!(i),
+                PrefValue::String(s) => json!(s)
+            };
+            (pref.name, json!({ "Value": value, "Status": status }))
+        })
+        .collect::<Map<_, _>>();
+
+    Value::Object(preferences)
+}
+
+// Firefox only lets the Preferences policy touch prefs under these prefixes
+fn is_policy_pref(name: &str) -> bool {
+    const PREFIXES: [&str; 23] = [
+        "accessibility.",
+        "alerts.",
+        "app.update.",
+        "browser.",
+        "datareporting.policy.",
+        "dom.",
+        "extensions.",
+        "general.autoScroll",
+        "general.smoothScroll",
+        "geo.",
+        "gfx.",
+        "intl.",
+        "layers.",
+        "layout.",
+        "media.",
+        "network.",
+        "pdfjs.",
+        "places.",
+        "print.",
+        "signon.",
+        "spellchecker.",
+        "ui.",
+        "widget."
+    ];
 
-    serde_json::to_string(&json!({"policies": policies}))
+    PREFIXES.iter().any(|prefix| name.starts_with(prefix))
 }
@@ -0,0 +1,220 @@
+use crate::{
+    browser::profile::BrowserProfile, firefox::common::prefs::{Pref, PrefValue, parse_prefs}, util::args
+};
+use color_eyre::eyre::{ContextCompat, WrapErr};
+use serde_json::{Map, Value, json};
+use std::{collections::HashSet, fs, path::Path};
+use tracing::{debug, warn};
+
+const STATE_PREF: &str = "browser.uiCustomization.state";
+// Restored by Firefox when switching back to horizontal tabs
+const BACKUP_PREF: &str = "browser.uiCustomization.horizontalTabsBackup";
+const VERTICAL_TABS_AREA: &str = "vertical-tabs";
+const TABS_AREA: &str = "TabsToolbar";
+// What Firefox starts from when a profile was never customized
+const STATE_VERSION: u64 = 22;
+
+// Where widgets go, every area not mentioned is left as it is
+struct Layout {
+    placements: Vec<(String, Vec<String>)>,
+    // Sent back to the customization palette
+    remove: Vec<String>
+}
+
+impl Layout {
+    // clenzy's layout, or the one from --toolbar-layout
+    fn load() -> color_eyre::Result<Self> {
+        let layout = match &args().toolbar_layout {
+            Some(path) => fs::read_to_string(path)
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))?,
+            None => include_str!("../../snippets/firefox/toolbar_layout.json").to_owned()
+        };
+
+        let layout = serde_json::from_str::<Value>(&layout).wrap_err("Invalid toolbar layout")?;
+        let strings = |value: &Value| {
+            value
+                .as_array()
+                .map(|v| v.iter().filter_map(Value::as_str).map(String::from).collect::<Vec<_>>())
+        };
+
+        let placements = layout
+            .get("placements")
+            .and_then(Value::as_object)
+            .wrap_err("Toolbar layout has no placements")?
+            .iter()
+            .map(|(area, widgets)| {
+                let widgets = strings(widgets)
+                    .wrap_err_with(|| format!("Placements of {area} aren't a list of widgets"))?;
+                Ok((area.clone(), widgets))
+            })
+            .collect::<color_eyre::Result<Vec<_>>>()?;
+        let remove = layout.get("remove").and_then(strings).unwrap_or_default();
+
+        Ok(Self { placements, remove })
+    }
+
+    // The same layout with horizontal tabs, the vertical tab widgets go back to the tab strip
+    fn horizontal(&self) -> Self {
+        let mut placements = Vec::<(String, Vec<String>)>::new();
+        for (area, widgets) in &self.placements {
+            let area = if area == VERTICAL_TABS_AREA { TABS_AREA } else { area.as_str() };
+            match placements.iter_mut().find(|(a, _)| a == area) {
+                Some((_, placed)) => placed.extend(widgets.iter().cloned()),
+                None => placements.push((area.to_owned(), widgets.clone()))
+            }
+        }
+
+        Self { placements, remove: self.remove.clone() }
+    }
+}
+
+// The toolbar state for a profile, built from what it has now so widgets the user added stay. With
+// vertical tabs, the horizontal backup follows the same layout.
+pub fn customization_prefs(profile: &BrowserProfile) -> color_eyre::Result<Vec<Pref>> {
+    let layout = Layout::load()?;
+    let current = fs::read_to_string(profile.path.join("prefs.js"))
+        .ok()
+        .and_then(|prefs_js| parse_prefs(&prefs_js).into_iter().find(|p| p.name == STATE_PREF))
+        .and_then(|p| match p.value {
+            PrefValue::String(state) => serde_json::from_str::<Value>(&state).ok(),
+            _ => None
+        });
+    let installed = installed_extension_widgets(&profile.path);
+
+    let pref = |name: &str, state: Value| Pref {
+        name: name.to_owned(),
+        value: PrefValue::String(state.to_string())
+    };
+
+    if !args().vertical_tabs {
+        return Ok(vec![pref(STATE_PREF, build(current, &layout, installed.as_ref(), false))]);
+    }
+
+    let backup = build(current.clone(), &layout.horizontal(), installed.as_ref(), false);
+    let state = build(current, &layout, installed.as_ref(), true);
+    Ok(vec![pref(STATE_PREF, state), pref(BACKUP_PREF, backup)])
+}
+
+fn build(
+    current: Option<Value>,
+    layout: &Layout,
+    installed: Option<&HashSet<String>>,
+    vertical_tabs: bool
+) -> Value {
+    let mut state = current
+        .and_then(|s| match s {
+            Value::Object(state) if state.get("placements").is_some_and(Value::is_object) => {
+                Some(state)
+            }
+            _ => None
+        })
+        .unwrap_or_else(|| {
+            debug!("No toolbar state yet, starting from an empty one");
+            let state = json!({
+                "placements": {},
+                "seen": [],
+                "dirtyAreaCache": [],
+                "currentVersion": STATE_VERSION,
+                "newElementCount": 0
+            });
+            state.as_object().cloned().unwrap_or_default()
+        });
+
+    // Buttons of extensions that aren't installed in this profile
+    let missing =
+        |w: &str| w.ends_with("-browser-action") && installed.is_some_and(|i| !i.contains(w));
+
+    let wanted = layout
+        .placements
+        .iter()
+        .filter(|(area, _)| vertical_tabs || area != VERTICAL_TABS_AREA)
+        .map(|(area, widgets)| {
+            let widgets = widgets.iter().filter(|w| !missing(w)).cloned().collect::<Vec<_>>();
+            (area.as_str(), widgets)
+        })
+        .collect::<Vec<_>>();
+    let placed = wanted.iter().flat_map(|(_, w)| w).cloned().collect::<HashSet<_>>();
+
+    let mut placements = state
+        .get("placements")
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+    for widgets in placements.values_mut().filter_map(Value::as_array_mut) {
+        widgets.retain(|w| {
+            w.as_str().is_some_and(|w| {
+                !missing(w) && !placed.contains(w) && !layout.remove.iter().any(|r| r == w)
+            })
+        });
+    }
+
+    // Our order first, then whatever the user put in the area themselves
+    for (area, widgets) in &wanted {
+        let added = placements.get(*area).and_then(Value::as_array).cloned().unwrap_or_default();
+        let widgets = widgets.iter().map(|w| json!(w)).chain(added).collect::<Vec<_>>();
+        placements.insert((*area).to_owned(), Value::Array(widgets));
+    }
+    state.insert(String::from("placements"), Value::Object(placements));
+
+    // Seen widgets aren't placed again by Firefox, and dirty areas keep their placements
+    extend_unique(&mut state, "seen", placed.iter().map(String::as_str));
+    extend_unique(&mut state, "dirtyAreaCache", wanted.iter().map(|(area, _)| *area));
+
+    Value::Object(state)
+}
+
+fn extend_unique<'a>(
+    state: &mut Map<String, Value>,
+    key: &str,
+    items: impl Iterator<Item = &'a str>
+) {
+    let list = state.entry(key).or_insert_with(|| json!([]));
+    if !list.is_array() {
+        *list = json!([]);
+    }
+
+    if let Some(list) = list.as_array_mut() {
+        for item in items {
+            if !list.iter().any(|i| i.as_str() == Some(item)) {
+                list.push(json!(item));
+            }
+        }
+    }
+}
+
+// Widget ids of the toolbar buttons every installed extension can have, None if unknown
+fn installed_extension_widgets(profile: &Path) -> Option<HashSet<String>> {
+    let extensions = match fs::read_to_string(profile.join("extensions.json")) {
+        Ok(extensions) => extensions,
+        Err(why) => {
+            debug!(err = ?why, "Couldn't read extensions.json, keeping every extension button");
+            return None;
+        }
+    };
+
+    let Ok(extensions) = serde_json::from_str::<Value>(&extensions) else {
+        warn!("Failed to parse extensions.json, keeping every extension button");
+        return None;
+    };
+
+    let widgets = extensions
+        .get("addons")
+        .and_then(Value::as_array)?
+        .iter()
+        .filter(|a| a.get("type").and_then(Value::as_str) == Some("extension"))
+        .filter_map(|a| a.get("id").and_then(Value::as_str))
+        .map(|id| format!("{}-browser-action", widget_id(id)))
+        .collect::<HashSet<_>>();
+
+    debug!("Found {} installed extensions", widgets.len());
+    Some(widgets)
+}
+
+// Firefox's makeWidgetId, uBlock0@raymondhill.net becomes ublock0_raymondhill_net
+fn widget_id(extension_id: &str) -> String {
+    extension_id
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '_' | '-') { c } else { '_' })
+        .collect()
+}
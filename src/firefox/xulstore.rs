@@ -1,10 +1,10 @@
-use crate::util::get_or_insert_obj;
+use crate::util::{get_or_insert_obj, transaction::Transaction};
 use color_eyre::eyre::{bail, ContextCompat};
 use serde_json::{json, Value};
 use std::{fs, path::Path};
 use tracing::{debug, warn};
 
-pub fn xulstore(root: &Path) -> color_eyre::Result<()> {
+pub fn xulstore(root: &Path, transaction: &mut Transaction) -> color_eyre::Result<()> {
     let path = root.join("xulstore.json");
     if !path.exists() {
         warn!(path = %path.display(), "xulstore.json does not exist");
@@ -30,5 +30,5 @@ pub fn xulstore(root: &Path) -> color_eyre::Result<()> {
         debug!("Collapsed tabs toolbar");
     }
 
-    fs::write(&path, serde_json::to_string(&xulstore)?).map_err(color_eyre::eyre::Error::from)
+    transaction.write(&path, serde_json::to_string(&xulstore)?)
 }
@@ -5,21 +5,24 @@ mod util;
 mod zen;
 
 use crate::{
-    brave::Brave, browser::Browser, firefox::Firefox, util::{RenderedBrowser, check_if_running, start_fetch_resource}, zen::Zen
+    brave::Brave, browser::{Browser, installation::Installation}, firefox::Firefox, util::{RenderedBrowser, args, start_fetch_resource, users}, zen::Zen
 };
-use clap::{ArgAction, Parser};
+use color_eyre::eyre::bail;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use inquire::MultiSelect;
 use std::{
-    env, sync::{LazyLock, OnceLock}
+    env, path::PathBuf, process, sync::{LazyLock, OnceLock}
 };
-use sysinfo::System;
-use tracing::{debug_span, info, warn};
+use tracing::{debug, debug_span, info, warn};
 use util::logging::{setup_logging, success};
 use crate::util::process_single_policies;
 
 #[derive(Parser, Default)]
 #[command(version)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Print extra debug information (max 3 levels with -vvv)
     #[clap(short, long, action = ArgAction::Count, default_value_t = 0)]
     pub verbose: u8,
@@ -36,27 +39,409 @@ pub struct Args {
     #[clap(long = "no-backup", action = ArgAction::SetFalse, default_value_t = true)]
     pub backup: bool,
 
+    /// Directory to keep backups in, defaults to clenzy/backups in the XDG state directory
+    #[clap(long = "backup-dir", value_name = "DIR")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Backups to keep of each profile and file, all of them are kept by default
+    #[clap(long = "keep-backups", value_name = "N")]
+    pub keep_backups: Option<usize>,
+
+    /// Remove backups older than this many days, the newest backup is always kept
+    #[clap(long = "backup-max-age", value_name = "DAYS")]
+    pub backup_max_age: Option<u64>,
+
+    /// Remove the oldest backups once all of them take up more than this many megabytes
+    #[clap(long = "backup-max-size", value_name = "MB")]
+    pub backup_max_size: Option<u64>,
+
+    /// What backups of whole profiles keep
+    #[clap(
+        long = "backup-mode",
+        value_enum,
+        value_name = "MODE",
+        default_value_t = BackupMode::Minimal
+    )]
+    pub backup_mode: BackupMode,
+
+    /// Archive format for backups of whole profiles
+    #[clap(
+        long = "backup-format",
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = BackupFormat::Zip
+    )]
+    pub backup_format: BackupFormat,
+
+    /// Encrypt backups with a passphrase, asked for unless it's in the environment or a file
+    #[clap(long = "encrypt-backups", default_value_t = false)]
+    pub encrypt_backups: bool,
+
+    /// Encrypt backups to this age public key instead of a passphrase, can be repeated
+    #[clap(long = "backup-recipient", value_name = "KEY")]
+    pub backup_recipients: Vec<String>,
+
+    /// Read the backup passphrase from the first line of this file, for unattended runs
+    #[clap(long = "backup-passphrase-file", value_name = "FILE")]
+    pub backup_passphrase_file: Option<PathBuf>,
+
+    /// Age identity file that decrypts backups made with --backup-recipient
+    #[clap(long = "backup-identity", value_name = "FILE")]
+    pub backup_identity: Option<PathBuf>,
+
     /// Disable search suggestions and prefetching. Every word in the URL bar you type will be sent to your search provider if search suggestions are enabled.
     #[clap(long = "no-search-suggestions", action = ArgAction::SetFalse, default_value_t = true)]
     pub search_suggestions: bool,
 
+    /// Wait for browsers that are using a profile to close, instead of prompting
+    #[clap(long = "wait", default_value_t = false)]
+    pub wait: bool,
+
+    /// Close browsers that are using a profile, killing them if they don't exit in time
+    #[clap(long = "close-browsers", default_value_t = false)]
+    pub close_browsers: bool,
+
+    /// Seconds to wait for a browser to release a profile
+    #[clap(long = "lock-timeout", value_name = "SECS", default_value_t = 60)]
+    pub lock_timeout: u64,
+
     /// Enable creating policy files
     #[clap(long = "policies", short = 'P', default_value_t = false)]
     pub policies: bool,
 
+    /// How strongly policies and prefs are enforced, recommended ones can still be changed by users
+    #[clap(
+        long = "enforcement",
+        value_enum,
+        value_name = "LEVEL",
+        default_value_t = Enforcement::Managed
+    )]
+    pub enforcement: Enforcement,
+
+    /// Debloat every human account on this machine (requires root, Linux only)
+    #[clap(long = "all-users", default_value_t = false)]
+    pub all_users: bool,
+
+    /// Debloat only this user, can be repeated (requires root, Linux only)
+    #[clap(long = "user", value_name = "NAME")]
+    pub users: Vec<String>,
+
+    /// Lowest UID treated as a human account with --all-users (defaults to /etc/login.defs)
+    #[clap(long = "min-uid", value_name = "UID")]
+    pub min_uid: Option<u32>,
+
+    /// Highest UID treated as a human account with --all-users (defaults to /etc/login.defs)
+    #[clap(long = "max-uid", value_name = "UID")]
+    pub max_uid: Option<u32>,
+
+    /// Tool to gain root with when writing system files, detected if not set (Linux only)
+    #[clap(long = "elevate-with", value_enum, value_name = "TOOL")]
+    pub elevate_with: Option<Elevator>,
+
+    /// Also install prefs into the app folder so new profiles start debloated, comma separated
+    #[clap(long = "app-defaults", value_enum, value_name = "KIND", value_delimiter = ',')]
+    pub app_defaults: Vec<AppDefaults>,
+
+    /// Lock the prefs installed with --app-defaults autoconfig so they can't be changed
+    #[clap(long = "lock-prefs", default_value_t = false)]
+    pub lock_prefs: bool,
+
+    /// How Firefox and Zen prefs are applied, prefsjs lets them be changed in settings afterwards
+    #[clap(
+        long = "firefox-mode",
+        value_enum,
+        value_name = "MODE",
+        default_value_t = FirefoxMode::Userjs
+    )]
+    pub firefox_mode: FirefoxMode,
+
+    /// Firefox toolbar layout to apply instead of clenzy's, as JSON with placements and remove
+    #[clap(long = "toolbar-layout", value_name = "FILE")]
+    pub toolbar_layout: Option<PathBuf>,
+
+    /// CSS presets to put into userChrome.css and userContent.css, comma separated
+    #[clap(long = "css-preset", value_enum, value_name = "PRESET", value_delimiter = ',')]
+    pub css_presets: Vec<CssPreset>,
+
+    /// CSS file to add to userChrome.css of every Firefox and Zen profile, can be repeated
+    #[clap(long = "user-chrome", value_name = "FILE")]
+    pub user_chrome: Vec<PathBuf>,
+
+    /// CSS file to add to userContent.css of every Firefox and Zen profile, can be repeated
+    #[clap(long = "user-content", value_name = "FILE")]
+    pub user_content: Vec<PathBuf>,
+
+    /// Whether sites can show notifications, blocked unless set
+    #[clap(long = "notifications", value_enum, value_name = "SETTING")]
+    pub notifications: Option<Permission>,
+
+    /// Whether sites can get your location, asked unless set
+    #[clap(long = "geolocation", value_enum, value_name = "SETTING")]
+    pub geolocation: Option<Permission>,
+
+    /// Whether sites can use the camera and microphone, left to the browser unless set
+    #[clap(long = "camera-mic", value_enum, value_name = "SETTING")]
+    pub camera_mic: Option<Permission>,
+
+    /// Whether media can play on its own, left to the browser unless set
+    #[clap(long = "autoplay", value_enum, value_name = "SETTING")]
+    pub autoplay: Option<Permission>,
+
+    /// Whether sites can open popups, left to the browser unless set
+    #[clap(long = "popups", value_enum, value_name = "SETTING")]
+    pub popups: Option<Permission>,
+
+    /// Permission for one site as kind:setting:origin, like notifications:allow:https://a.org
+    #[clap(long = "site-permission", value_name = "PERMISSION")]
+    pub site_permissions: Vec<String>,
+
+    /// Privacy hardening on top of debloating, strict also clears cookies when the browser closes
+    #[clap(long = "hardening", value_enum, value_name = "LEVEL", default_value_t = Hardening::Off)]
+    pub hardening: Hardening,
+
+    /// Site whose cookies are always kept, so it stays logged in with --hardening strict
+    #[clap(long = "keep-cookies", value_name = "ORIGIN")]
+    pub keep_cookies: Vec<String>,
+
+    /// Set a Brave flag as name@option, name=enabled, name=disabled or name=default
+    #[clap(long = "brave-flag", value_name = "FLAG")]
+    pub brave_flags: Vec<String>,
+
+    /// Only set the Brave flags given with --brave-flag, not clenzy's own
+    #[clap(long = "no-brave-flags", action = ArgAction::SetFalse, default_value_t = true)]
+    pub brave_flags_default: bool,
+
+    /// Also install Brave's initial preferences so new profiles start debloated
+    #[clap(long = "initial-preferences", default_value_t = false)]
+    pub initial_preferences: bool,
+
+    #[clap(long = "brave-initial-preferences-elevated", default_value_t = false, hide = true)]
+    pub brave_initial_preferences_elevated: bool,
+
+    #[clap(long = "app-defaults-elevated", default_value_t = false, hide = true)]
+    pub app_defaults_elevated: bool,
+
     #[clap(long = "windows-brave-policies", default_value_t = false, hide = true)]
     pub windows_brave_policies: bool,
 
-    #[clap(long = "linux-brave-policies", default_value_t = false, hide = true)]
-    pub linux_brave_policies: bool,
+    #[clap(long = "privileged-helper", default_value_t = false, hide = true)]
+    pub privileged_helper: bool,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Inspect the browser policies on this system
+    Policies {
+        #[command(subcommand)]
+        command: PoliciesCommand
+    },
+    /// Check Firefox and Zen prefs against the installed builds
+    Prefs {
+        #[command(subcommand)]
+        command: PrefsCommand
+    },
+    /// Remove the prefs an older user.js from clenzy left behind in prefs.js
+    CleanPrefs {
+        /// Compare against this user.js instead of the one clenzy would install
+        #[clap(long = "user-js", value_name = "FILE")]
+        user_js: Option<PathBuf>
+    },
+    /// Inspect Brave's initial preferences on this system
+    InitialPreferences {
+        #[command(subcommand)]
+        command: InitialPreferencesCommand
+    },
+    /// Manage the backups clenzy made before changing files
+    Backups {
+        #[command(subcommand)]
+        command: BackupsCommand
+    },
+    /// List or set the experiments on Brave's flags page in every data folder
+    Flags {
+        #[command(subcommand)]
+        command: FlagsCommand
+    },
+    /// Show the Brave features and flags forced on or off, and whether installed Brave has them
+    Features,
+    /// Write every file clenzy would apply into a directory, without touching this system
+    Export {
+        /// Directory to write the files and their manifest into
+        #[clap(long = "out", value_name = "DIR")]
+        out: PathBuf
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum BackupsCommand {
+    /// List every backup in the store, and backups older versions left next to profiles
+    List,
+    /// Remove the backups that fall outside of the retention options
+    Prune {
+        /// Also remove backups older versions left next to profiles
+        #[clap(long = "legacy", default_value_t = false)]
+        legacy: bool,
+
+        /// Only show what would be removed
+        #[clap(long = "dry-run", default_value_t = false)]
+        dry_run: bool
+    },
+    /// Check backup archives against the hashes stored inside them
+    Verify {
+        /// Archives to check, every archive in the store if none are given
+        paths: Vec<PathBuf>
+    },
+    /// Put backups back where they were taken from, decrypting them if needed
+    Restore {
+        /// Backups to restore, absolute or relative to the backup directory
+        paths: Vec<PathBuf>,
+
+        /// Restore every backup made by this run, as shown by `backups list`
+        #[clap(long = "run", value_name = "RUN")]
+        run: Option<String>,
+
+        /// Restore a single backup here instead
+        #[clap(long = "to", value_name = "PATH")]
+        to: Option<PathBuf>
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum FlagsCommand {
+    /// List the flags set in each data folder, and which of them clenzy sets
+    List,
+    /// Set flags without debloating anything else
+    Set {
+        /// Flags as name@option, name=enabled, name=disabled or name=default
+        #[clap(required = true)]
+        flags: Vec<String>
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum PrefsCommand {
+    /// Report prefs in user.js that the build doesn't know, has with another type, or defaults to
+    Check {
+        /// Check this user.js instead of the one clenzy would install
+        #[clap(long = "user-js", value_name = "FILE")]
+        user_js: Option<PathBuf>
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum InitialPreferencesCommand {
+    /// Check whether Brave's initial preferences are present and current
+    Check
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum PoliciesCommand {
+    /// Validate the policy files already on this system against the bundled schemas
+    Check
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Enforcement {
+    /// Policies are only defaults, users can change them back
+    Recommended,
+    /// Policies are enforced, but Firefox's policy groups stay unlocked
+    #[default]
+    Managed,
+    /// Everything is enforced, and prefs are locked wherever clenzy can lock them
+    Locked
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackupMode {
+    /// Leaves out caches, extensions and site data, which can be huge
+    #[default]
+    Minimal,
+    /// Keeps every file in the profile, so a restore brings back extensions and site data
+    Full
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackupFormat {
+    #[default]
+    Zip,
+    /// Compressed on every core, much faster for large profiles
+    TarZst
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FirefoxMode {
+    /// user.js in every profile, which resets the prefs on every start
+    #[default]
+    Userjs,
+    /// Written into prefs.js once with the browser closed, no user.js is left behind
+    Prefsjs
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CssPreset {
+    /// Close buttons on tabs
+    HideTabClose,
+    /// The Firefox View button
+    HideFirefoxView,
+    /// The button listing every tab
+    HideAllTabsButton,
+    /// The label in private windows
+    HidePrivateBrowsingIndicator,
+    /// The logo on the new tab page
+    HideNewTabLogo
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Hardening {
+    /// Standard tracking protection, like the browsers ship with
+    #[default]
+    Off,
+    /// Strict tracking protection, HTTPS only and fingerprinting protection
+    Balanced,
+    /// Also resists fingerprinting harder and clears cookies and cache on exit
+    Strict
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    Allow,
+    Ask,
+    /// Sites can't ask either
+    Block
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentKind {
+    Notifications,
+    Geolocation,
+    CameraMic,
+    /// Can't be asked for, ask blocks it
+    Autoplay,
+    /// Can't be asked for, ask blocks them
+    Popups
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Elevator {
+    Sudo,
+    Doas,
+    Pkexec,
+    Run0
+}
 
-    #[clap(long = "linux-firefox-policies", default_value_t = false, hide = true)]
-    pub linux_firefox_policies: bool,
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppDefaults {
+    /// defaults/pref/clenzy.js and clenzy.cfg, using defaultPref or lockPref
+    Autoconfig,
+    /// distribution/distribution.ini
+    Distribution
 }
 
 pub static ARGS: OnceLock<Args> = OnceLock::new();
-pub static BROWSERS: LazyLock<Vec<RenderedBrowser>> =
-    LazyLock::new(|| render_browsers!(Firefox, Brave, Zen));
+pub static BROWSERS: LazyLock<Vec<RenderedBrowser>> = LazyLock::new(browsers);
+
+fn browsers() -> Vec<RenderedBrowser> {
+    render_browsers!(Firefox, Brave, Zen)
+}
 
 fn main() -> color_eyre::Result<()> {
     if cfg!(debug_assertions) {
@@ -70,6 +455,30 @@ fn main() -> color_eyre::Result<()> {
 
     setup_logging(args)?;
 
+    #[cfg(target_os = "linux")]
+    if args.privileged_helper {
+        return util::elevate::run_helper();
+    }
+
+    // Before anything is written, so a typo doesn't leave browsers half done or export drop them
+    util::content_settings::exceptions()?;
+    util::hardening::kept_cookies()?;
+
+    if let Some(command) = &args.command {
+        return run_command(command);
+    }
+
+    if args.enforcement == Enforcement::Locked
+        && !args.policies
+        && !args.app_defaults.contains(&AppDefaults::Autoconfig)
+    {
+        warn!("--enforcement locked needs --policies or --app-defaults autoconfig to pin prefs");
+    }
+
+    if args.all_users || !args.users.is_empty() {
+        return debloat_system_wide(args);
+    }
+
     let installations = BROWSERS
         .iter()
         .flat_map(|browser| &browser.installations)
@@ -81,44 +490,213 @@ fn main() -> color_eyre::Result<()> {
         return Ok(());
     }
 
+    if args.app_defaults_elevated {
+        return firefox::common::app_defaults::install_elevated(&installations);
+    }
+
+    if args.brave_initial_preferences_elevated {
+        return brave::initial_preferences::install_elevated(&installations);
+    }
 
     process_single_policies(args, &installations[..], false);
 
+    fetch_resources();
+
+    let installations = select_installations(installations, "Select browsers to debloat")?;
+    if installations.is_empty() {
+        return Ok(());
+    }
+
+    debloat_installations(&installations);
+
+    process_single_policies(args, &installations[..], true);
+
+    if args.backup {
+        util::backups::enforce_retention();
+    }
+
+    success("Done");
+    Ok(())
+}
+
+fn run_command(command: &Command) -> color_eyre::Result<()> {
+    let installations =
+        || BROWSERS.iter().flat_map(|browser| &browser.installations).collect::<Vec<_>>();
+
+    match command {
+        Command::Policies { command: PoliciesCommand::Check } => {
+            if !util::check_policies(&installations()) {
+                process::exit(1);
+            }
+        }
+        Command::Prefs { command: PrefsCommand::Check { user_js } } => {
+            firefox::common::defaults::check_command(&installations(), user_js.as_deref())?;
+        }
+        Command::CleanPrefs { user_js } => {
+            firefox::common::clean_prefs::clean_command(&installations(), user_js.as_deref())?;
+        }
+        Command::InitialPreferences { command: InitialPreferencesCommand::Check } => {
+            if !brave::initial_preferences::check(&installations()) {
+                process::exit(1);
+            }
+        }
+        Command::Backups { command: BackupsCommand::List } => {
+            util::backups::list(&installations())?;
+        }
+        Command::Backups { command: BackupsCommand::Prune { legacy, dry_run } } => {
+            util::backups::prune_command(&installations(), *legacy, *dry_run)?;
+        }
+        Command::Backups { command: BackupsCommand::Verify { paths } } => {
+            if !util::backups::verify_command(paths)? {
+                process::exit(1);
+            }
+        }
+        Command::Backups { command: BackupsCommand::Restore { paths, run, to } } => {
+            util::backups::restore_command(paths, run.as_deref(), to.as_deref())?;
+        }
+        Command::Flags { command: FlagsCommand::List } => {
+            brave::flags_list_command(&installations());
+        }
+        Command::Flags { command: FlagsCommand::Set { flags } } => {
+            brave::flags_set_command(&installations(), flags)?;
+        }
+        Command::Features => brave::features_command(&installations()),
+        Command::Export { out } => util::export::export(out)?
+    }
+
+    Ok(())
+}
+
+fn fetch_resources() {
     for browser in &*BROWSERS {
         if let Some(fetch_resources) = browser.fetch_resources {
             start_fetch_resource(fetch_resources, browser.name);
         }
     }
+}
+
+fn select_installations<'a>(
+    installations: Vec<&'a Installation>,
+    message: &str
+) -> color_eyre::Result<Vec<&'a Installation>> {
+    if args().auto_confirm || installations.is_empty() {
+        return Ok(installations);
+    }
 
     let browsers_len = installations.len();
-    let installations = if args.auto_confirm {
-        installations
-    } else {
-        MultiSelect::new("Select browsers to debloat", installations)
-            .with_all_selected_by_default()
-            .with_page_size(browsers_len)
-            .prompt()?
-    };
+    let installations = MultiSelect::new(message, installations)
+        .with_all_selected_by_default()
+        .with_page_size(browsers_len)
+        .prompt()?;
 
-    if installations.is_empty() {
+    Ok(installations)
+}
+
+// Returns each installation with whether it was debloated successfully
+fn debloat_installations(installations: &[&Installation]) -> Vec<(String, bool)> {
+    let mut results = Vec::with_capacity(installations.len());
+    for installation in installations {
+        let span = debug_span!("debloat", browser = %installation.browser_name);
+        let _enter = span.enter();
+
+        let ok = match installation.debloat() {
+            Ok(()) => {
+                success(&format!("Finished debloating {}", installation.browser_name));
+                true
+            }
+            Err(why) => {
+                warn!(err = ?why, "Failed to debloat {}", installation.browser_name);
+                false
+            }
+        };
+
+        results.push((installation.to_string(), ok));
+    }
+
+    results
+}
+
+// Runs discovery and debloating against every selected user's home
+fn debloat_system_wide(args: &Args) -> color_eyre::Result<()> {
+    if !cfg!(target_os = "linux") {
+        bail!("--all-users and --user are only supported on Linux");
+    }
+
+    if !users::is_root() {
+        bail!("--all-users and --user require root");
+    }
+
+    let users = users::system_users(args)?;
+    if users.is_empty() {
+        info!("No users found to debloat");
         return Ok(());
     }
 
-    let mut system = System::new();
+    let names = users.iter().map(|u| u.name.as_str()).collect::<Vec<_>>();
+    info!("Found {} users: {}", users.len(), names.join(", "));
+    fetch_resources();
 
-    for installation in &installations {
-        let span = debug_span!("debloat", browser = %installation.browser_name);
+    let mut debloated = Vec::new();
+    let mut summary = Vec::with_capacity(users.len());
+    for user in &users {
+        let span = debug_span!("user", user = %user.name);
         let _enter = span.enter();
 
-        check_if_running(&mut system, installation.browser_name);
+        let browsers = users::with_user(user, browsers);
+        let installations = browsers
+            .iter()
+            .flat_map(|browser| &browser.installations)
+            .filter(|installation| installation.is_valid())
+            .collect::<Vec<_>>();
 
-        match installation.debloat() {
-            Ok(()) => success(&format!("Finished debloating {}", installation.browser_name)),
-            Err(why) => warn!(err = ?why, "Failed to debloat {}", installation.browser_name)
+        if installations.is_empty() {
+            debug!("No browsers found for {user}");
+            summary.push((user, Vec::new()));
+            continue;
         }
+
+        let installations = select_installations(
+            installations,
+            &format!("Select browsers to debloat for {}", user.name)
+        )?;
+
+        let results = users::with_user(user, || {
+            let results = debloat_installations(&installations);
+            if args.backup {
+                util::backups::enforce_retention();
+            }
+            results
+        });
+        debloated.extend(installations.into_iter().cloned());
+        summary.push((user, results));
     }
 
-    process_single_policies(args, &installations[..], true);
+    process_single_policies(args, &debloated.iter().collect::<Vec<_>>(), true);
+
+    // Policy backups go into root's own store
+    if args.backup {
+        util::backups::enforce_retention();
+    }
+
+    for (user, results) in summary {
+        let (ok, failed): (Vec<_>, Vec<_>) = results.into_iter().partition(|(_, ok)| *ok);
+        let names = |r: Vec<(String, bool)>| {
+            r.into_iter().map(|(name, _)| name).collect::<Vec<_>>().join(", ")
+        };
+
+        if ok.is_empty() && failed.is_empty() {
+            info!("{}: no browsers found", user.name);
+            continue;
+        }
+
+        if !ok.is_empty() {
+            success(&format!("{}: debloated {}", user.name, names(ok)));
+        }
+
+        if !failed.is_empty() {
+            warn!("{}: failed to debloat {}", user.name, names(failed));
+        }
+    }
 
     success("Done");
     Ok(())
@@ -1,15 +1,262 @@
+use crate::{
+    BackupFormat, BackupMode, browser::{installation::Installation, profile::BrowserProfile}, util::{args, backups::{BackupWriter, Slot, format_size}, encryption, logging::success}
+};
+use clap::ValueEnum;
 use color_eyre::eyre::{bail, Context, ContextCompat};
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
 use std::{
-    fs, fs::{DirEntry, File}, io, io::{BufReader, Read, Write}, path::Path
+    collections::HashMap, fmt::Write as _, fs, fs::{DirEntry, File}, io, io::{BufReader, Read, Write}, path::{Path, PathBuf}, time::{Duration, Instant}
 };
-use tracing::{debug, trace};
-use zip::{write::SimpleFileOptions, ZipWriter};
+use tracing::{debug, info, trace, warn};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+// Every archive ends with the size and SHA-256 of each file in it
+const MANIFEST_FILE: &str = "clenzy-manifest.json";
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+trait Archive {
+    fn add_dir(&mut self, name: &str, metadata: &fs::Metadata) -> io::Result<()>;
+    fn add_file(
+        &mut self,
+        name: &str,
+        metadata: &fs::Metadata,
+        data: &mut dyn Read
+    ) -> io::Result<()>;
+    fn add_bytes(&mut self, name: &str, data: &[u8]) -> io::Result<()>;
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+struct Zip {
+    zip: ZipWriter<File>,
+    options: SimpleFileOptions
+}
+
+impl Zip {
+    fn new(file: File) -> Self {
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .large_file(true);
+        Self { zip: ZipWriter::new(file), options }
+    }
+}
+
+impl Archive for Zip {
+    fn add_dir(&mut self, name: &str, _metadata: &fs::Metadata) -> io::Result<()> {
+        self.zip.add_directory(name, self.options).map_err(io::Error::other)
+    }
+
+    fn add_file(
+        &mut self,
+        name: &str,
+        _metadata: &fs::Metadata,
+        data: &mut dyn Read
+    ) -> io::Result<()> {
+        self.zip.start_file(name, self.options).map_err(io::Error::other)?;
+        io::copy(data, &mut self.zip).map(|_| ())
+    }
+
+    fn add_bytes(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        self.zip.start_file(name, self.options).map_err(io::Error::other)?;
+        self.zip.write_all(data)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.zip.finish().map_err(io::Error::other)?.sync_all()
+    }
+}
+
+struct TarZst {
+    tar: tar::Builder<zstd::Encoder<'static, BackupWriter>>
+}
+
+impl TarZst {
+    fn new(output: BackupWriter) -> io::Result<Self> {
+        let mut encoder = zstd::Encoder::new(output, 0)?;
+        // Big profiles are the reason to pick this format, so use every core
+        let threads = std::thread::available_parallelism().map_or(1, std::num::NonZero::get);
+        encoder.multithread(u32::try_from(threads).unwrap_or(1))?;
+        Ok(Self { tar: tar::Builder::new(encoder) })
+    }
+}
+
+impl Archive for TarZst {
+    fn add_dir(&mut self, name: &str, metadata: &fs::Metadata) -> io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(metadata);
+        header.set_size(0);
+        self.tar.append_data(&mut header, name, io::empty())
+    }
+
+    fn add_file(
+        &mut self,
+        name: &str,
+        metadata: &fs::Metadata,
+        data: &mut dyn Read
+    ) -> io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(metadata);
+        // The header already promised this many bytes
+        self.tar.append_data(&mut header, name, data.take(metadata.len()))
+    }
+
+    fn add_bytes(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(u64::try_from(chrono::Utc::now().timestamp()).unwrap_or_default());
+        self.tar.append_data(&mut header, name, data)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.tar.into_inner()?.finish()?.finish()
+    }
+}
+
+struct Progress {
+    label: String,
+    total: u64,
+    done: u64,
+    last: Instant
+}
+
+impl Progress {
+    fn new(label: String, total: u64) -> Self {
+        Self { label, total, done: 0, last: Instant::now() }
+    }
+
+    // Only says something for backups that take a while
+    fn advance(&mut self, bytes: u64) {
+        self.done = self.done.saturating_add(bytes);
+        if self.last.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+
+        self.last = Instant::now();
+        let percent =
+            self.done.saturating_mul(100).checked_div(self.total).unwrap_or(100).min(100);
+        info!(
+            "{}: {percent}% ({} of {})",
+            self.label,
+            format_size(self.done),
+            format_size(self.total)
+        );
+    }
+}
+
+// Hashes everything read through it
+struct Hashing<'a, R> {
+    inner: R,
+    hasher: Sha256,
+    size: u64,
+    progress: Option<&'a mut Progress>
+}
+
+impl<'a, R: Read> Hashing<'a, R> {
+    fn new(inner: R, progress: Option<&'a mut Progress>) -> Self {
+        Self { inner, hasher: Sha256::new(), size: 0, progress }
+    }
+
+    fn finish(self) -> Value {
+        json!({ "size": self.size, "sha256": hex(&self.hasher.finalize()) })
+    }
+}
+
+impl<R: Read> Read for Hashing<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(buf.get(..n).unwrap_or_default());
+        self.size += n as u64;
+        if let Some(progress) = &mut self.progress {
+            progress.advance(n as u64);
+        }
+
+        Ok(n)
+    }
+}
+
+struct Writer {
+    archive: Box<dyn Archive>,
+    manifest: Map<String, Value>,
+    progress: Progress
+}
+
+impl Writer {
+    fn finish(mut self) -> color_eyre::Result<()> {
+        let mode = args().backup_mode.to_possible_value().map(|v| v.get_name().to_owned());
+        let manifest = json!({
+            "version": 1,
+            "mode": mode,
+            "files": self.manifest
+        });
+
+        self.archive
+            .add_bytes(MANIFEST_FILE, serde_json::to_string_pretty(&manifest)?.as_bytes())
+            .wrap_err("Failed to add manifest")?;
+        self.archive.finish().wrap_err("Failed to finish archive")
+    }
+}
+
+// Archives the whole profile into the backup store, minimal backups leave out anything in `skip`
+pub fn backup_profile(
+    installation: &Installation,
+    profile: &BrowserProfile,
+    skip: &[&str]
+) -> color_eyre::Result<()> {
+    let skip = match args().backup_mode {
+        BackupMode::Minimal => skip,
+        BackupMode::Full => &[]
+    };
+    // Zip needs to seek back over what it wrote, which an encrypted stream can't do
+    let format = if encryption::is_enabled() { BackupFormat::TarZst } else { args().backup_format };
+    let extension = match format {
+        BackupFormat::Zip => "zip",
+        BackupFormat::TarZst => "tar.zst"
+    };
+
+    let slot = Slot::profile(installation, &profile.path);
+    let (backup_path, output) = slot.create(&format!("profile.{extension}"))?;
+
+    let result = (|| {
+        let archive: Box<dyn Archive> = match (format, output) {
+            (BackupFormat::Zip, BackupWriter::Plain(file)) => Box::new(Zip::new(file)),
+            (BackupFormat::TarZst, output) => Box::new(TarZst::new(output)?),
+            (BackupFormat::Zip, BackupWriter::Encrypted(_)) => {
+                bail!("Zip backups can't be encrypted")
+            }
+        };
+        let total = total_size(&profile.path, skip);
+        let mut writer = Writer {
+            archive,
+            manifest: Map::new(),
+            progress: Progress::new(format!("Backing up {profile}"), total)
+        };
+
+        debug!("Creating backup archive at {}", backup_path.display());
+        for entry in fs::read_dir(&profile.path)? {
+            if let Err(why) = add_to_archive(&mut writer, entry, &profile.path, skip) {
+                warn!(err = ?why, "Failed to add entry to archive");
+            }
+        }
 
-pub fn add_to_archive(
-    zip: &mut ZipWriter<File>,
+        writer.finish()
+    })();
+
+    if let Err(why) = result {
+        let _ = fs::remove_file(&backup_path);
+        return Err(why);
+    }
+
+    debug!("Finished creating backup archive");
+    slot.record(&backup_path, "profile", Some(&profile.path))?;
+    success(&format!("Backup created for user profile {profile}"));
+    Ok(())
+}
+
+fn add_to_archive(
+    writer: &mut Writer,
     entry: io::Result<DirEntry>,
     prefix: &Path,
-    options: &SimpleFileOptions,
     skip: &[&str]
 ) -> color_eyre::Result<()> {
     let entry = entry?;
@@ -33,9 +280,9 @@ pub fn add_to_archive(
     };
 
     let r = if file_type.is_dir() {
-        add_dir_to_archive(zip, &abs_path, path, prefix, options, skip)
+        add_dir_to_archive(writer, &abs_path, path, prefix, skip)
     } else if file_type.is_file() {
-        add_file_to_archive(zip, &abs_path, path, options)
+        add_file_to_archive(writer, &abs_path, path)
     } else {
         trace!(path = %path.display(), file_type = ?file_type, "Skipping entry of bad type");
         return Ok(());
@@ -49,18 +296,17 @@ pub fn add_to_archive(
 }
 
 fn add_dir_to_archive(
-    zip: &mut ZipWriter<File>,
+    writer: &mut Writer,
     abs_path: &Path,
     path: &Path,
     prefix: &Path,
-    options: &SimpleFileOptions,
     skip: &[&str]
 ) -> color_eyre::Result<()> {
-    zip.add_directory(path.display().to_string(), *options)?;
+    writer.archive.add_dir(&entry_name(path), &fs::metadata(abs_path)?)?;
 
     let entries = fs::read_dir(abs_path)?;
     for entry in entries {
-        if let Err(why) = add_to_archive(zip, entry, prefix, options, skip) {
+        if let Err(why) = add_to_archive(writer, entry, prefix, skip) {
             debug!(err = ?why, "Failed to add entry to archive (nested)");
         }
     }
@@ -69,29 +315,216 @@ fn add_dir_to_archive(
 }
 
 fn add_file_to_archive(
-    zip: &mut ZipWriter<File>,
+    writer: &mut Writer,
     abs_path: &Path,
-    path: &Path,
-    options: &SimpleFileOptions
+    path: &Path
 ) -> color_eyre::Result<()> {
-    zip.start_file(path.display(), *options)?;
+    let file = File::open(abs_path).wrap_err("Failed to open file")?;
+    let metadata = file.metadata()?;
+    let name = entry_name(path);
 
     let mut reader =
-        BufReader::with_capacity(8192, File::open(abs_path).wrap_err("Failed to open file")?);
-    let mut buffer = [0u8; 8192];
+        Hashing::new(BufReader::with_capacity(8192, file), Some(&mut writer.progress));
+    writer.archive.add_file(&name, &metadata, &mut reader)?;
+
+    writer.manifest.insert(name, reader.finish());
+    Ok(())
+}
+
+// Archives always use forward slashes
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn total_size(dir: &Path, skip: &[&str]) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter(|e| !skip.iter().any(|s| e.file_name().to_string_lossy().contains(s)))
+        .map(|e| match e.file_type() {
+            Ok(t) if t.is_dir() => total_size(&e.path(), skip),
+            Ok(t) if t.is_file() => e.metadata().map(|m| m.len()).unwrap_or_default(),
+            _ => 0
+        })
+        .sum()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+// Encrypted archives end with .age on top of their format
+fn archive_format(path: &Path) -> Option<BackupFormat> {
+    let path = if encryption::is_encrypted(path) { Path::new(path.file_stem()?) } else { path };
+    let is_tar =
+        || Path::new(path.file_stem().unwrap_or_default()).extension() == Some("tar".as_ref());
+
+    match path.extension()?.to_str()? {
+        "zip" => Some(BackupFormat::Zip),
+        "zst" if is_tar() => Some(BackupFormat::TarZst),
+        _ => None
+    }
+}
 
-    loop {
-        let b = reader.read(&mut buffer)?;
-        if b == 0 {
-            break;
+pub fn is_archive(path: &Path) -> bool {
+    archive_format(path).is_some()
+}
+
+// Calls `visit` with the name and contents of every file in an archive
+fn for_each_file(
+    path: &Path,
+    visit: &mut dyn FnMut(&str, &mut dyn Read) -> color_eyre::Result<()>
+) -> color_eyre::Result<()> {
+    let Some(format) = archive_format(path) else {
+        bail!("{} isn't a backup archive", path.display());
+    };
+    let file = File::open(path).wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+
+    if format == BackupFormat::Zip {
+        if encryption::is_encrypted(path) {
+            bail!("Encrypted zip backups aren't supported, only .tar.zst.age");
         }
 
-        let bytes = buffer
-            .get(..b)
-            .wrap_err_with(|| format!("failed to index into byte {b} when zipping"))?;
-        zip.write_all(bytes)?;
+        let mut zip = ZipArchive::new(file).wrap_err("Failed to read zip")?;
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i)?;
+            if entry.is_file() {
+                let name = entry.name().to_owned();
+                visit(&name, &mut entry)?;
+            }
+        }
+    } else {
+        let mut tar = open_tar(path, file)?;
+        for entry in tar.entries().wrap_err("Failed to read archive")? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_file() {
+                let name = entry.path()?.to_string_lossy().into_owned();
+                visit(&name, &mut entry)?;
+            }
+        }
     }
 
-    zip.flush()?;
     Ok(())
 }
+
+fn open_tar(path: &Path, file: File) -> color_eyre::Result<tar::Archive<Box<dyn Read>>> {
+    let input: Box<dyn Read> = if encryption::is_encrypted(path) {
+        Box::new(encryption::decrypt(BufReader::new(file))?)
+    } else {
+        Box::new(file)
+    };
+
+    Ok(tar::Archive::new(Box::new(zstd::Decoder::new(input)?)))
+}
+
+// How much of an archive checked out
+pub struct Verified {
+    pub files: usize,
+    pub size: u64
+}
+
+// Checks every file in a backup archive against its manifest, before anything is restored from it
+pub fn verify(path: &Path) -> color_eyre::Result<Verified> {
+    let mut found = HashMap::new();
+    let mut manifest = None;
+    for_each_file(path, &mut |entry, data| {
+        if entry == MANIFEST_FILE {
+            let mut contents = String::new();
+            data.read_to_string(&mut contents)?;
+            manifest = Some(serde_json::from_str::<Value>(&contents)?);
+        } else {
+            let mut reader = Hashing::new(data, None);
+            io::copy(&mut reader, &mut io::sink())
+                .wrap_err_with(|| format!("Failed to read {entry}"))?;
+            found.insert(entry.to_owned(), reader.finish());
+        }
+
+        Ok(())
+    })?;
+
+    let files = manifest
+        .as_ref()
+        .and_then(|m| m.get("files"))
+        .and_then(Value::as_object)
+        .wrap_err("Archive has no manifest, it was made by an older version")?;
+
+    let mut problems = Vec::new();
+    for (entry, expected) in files {
+        match found.remove(entry) {
+            Some(actual) if actual == *expected => {}
+            Some(_) => problems.push(format!("{entry} doesn't match its hash")),
+            None => problems.push(format!("{entry} is missing"))
+        }
+    }
+    problems.extend(found.keys().map(|entry| format!("{entry} isn't in the manifest")));
+
+    if !problems.is_empty() {
+        problems.sort();
+        bail!("{} problems: {}", problems.len(), problems.join(", "));
+    }
+
+    let size = files.values().filter_map(|f| f.get("size")).filter_map(Value::as_u64).sum();
+    Ok(Verified { files: files.len(), size })
+}
+
+// The contents of one file in an archive, if it's there
+pub fn read_file(path: &Path, name: &str) -> color_eyre::Result<Option<Vec<u8>>> {
+    let mut contents = None;
+    for_each_file(path, &mut |entry, data| {
+        if contents.is_none() && entry.trim_start_matches("./") == name {
+            let mut buf = Vec::new();
+            data.read_to_end(&mut buf).wrap_err_with(|| format!("Failed to read {entry}"))?;
+            contents = Some(buf);
+        }
+
+        Ok(())
+    })?;
+
+    Ok(contents)
+}
+
+// Writes every file in an archive into `dest`, replacing the ones already there
+pub fn extract(path: &Path, dest: &Path) -> color_eyre::Result<usize> {
+    fs::create_dir_all(dest).wrap_err_with(|| format!("Failed to create {}", dest.display()))?;
+
+    let mut extracted = 0;
+    for_each_file(path, &mut |entry, data| {
+        if entry == MANIFEST_FILE {
+            return Ok(());
+        }
+
+        let target = dest.join(enclosed_path(entry)?);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = File::create(&target)
+            .wrap_err_with(|| format!("Failed to create {}", target.display()))?;
+        io::copy(data, &mut file).wrap_err_with(|| format!("Failed to extract {entry}"))?;
+        extracted += 1;
+        Ok(())
+    })?;
+
+    debug!(%extracted, "Extracted backup into {}", dest.display());
+    Ok(extracted)
+}
+
+// Never let an entry write outside of the folder it's extracted into
+fn enclosed_path(entry: &str) -> color_eyre::Result<PathBuf> {
+    let path = entry.split('/').filter(|c| !c.is_empty() && *c != ".").collect::<PathBuf>();
+    let escapes = path.components().any(|c| !matches!(c, std::path::Component::Normal(_)));
+    if escapes || path.as_os_str().is_empty() {
+        bail!("Archive entry {entry} points outside of the profile");
+    }
+
+    Ok(path)
+}
@@ -0,0 +1,821 @@
+use crate::{
+    brave::Brave, browser::{Browser, installation::{InstalledVia, Installation, Variant}}, util::{archive, args, encryption, lock::{LockKind, ensure_unlocked}, logging::success, timestamp, transaction::write_atomic, users}
+};
+use age::stream::StreamWriter;
+use color_eyre::eyre::{bail, ContextCompat, WrapErr};
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap, fs, fs::File, io, io::{Read, Write}, path::{Path, PathBuf}, sync::LazyLock
+};
+use tracing::{debug, info, warn};
+
+const INDEX_FILE: &str = "index.json";
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+const LEGACY_BRAVE_POLICIES: &str = "/etc/brave/policies/managed";
+
+// Every backup made by one run shares this, so they can be restored together
+static RUN: LazyLock<String> = LazyLock::new(timestamp);
+
+// Where a backup belongs in the store: <browser>/<installation>/<profile>
+#[derive(Clone, Debug)]
+pub struct Slot {
+    browser: String,
+    installation: String,
+    profile: String
+}
+
+impl Slot {
+    pub fn new(browser: &str, installation: &str, profile: &str) -> Self {
+        Self { browser: slug(browser), installation: slug(installation), profile: slug(profile) }
+    }
+
+    // Backups of a profile, or of files shared by every profile in a data folder
+    pub fn profile(installation: &Installation, profile_path: &Path) -> Self {
+        let profile = profile_path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        Self::new(
+            installation.browser_name,
+            &installation_key(installation.installed_via, installation.variant),
+            &profile
+        )
+    }
+
+    // Backups of system wide files, like policies
+    pub fn system(browser: &str, installed_via: InstalledVia, variant: Option<Variant>) -> Self {
+        Self::new(browser, &installation_key(installed_via, variant), "policies")
+    }
+
+    pub fn policies(installation: &Installation) -> Self {
+        Self::system(installation.browser_name, installation.installed_via, installation.variant)
+    }
+
+    // Picks a path for a new backup called `name`, creating the directories it goes in
+    pub fn path(&self, name: &str) -> color_eyre::Result<PathBuf> {
+        let dir = store_dir()?.join(&self.browser).join(&self.installation).join(&self.profile);
+        create_dirs(&dir)?;
+
+        let run = &*RUN;
+        let mut path = dir.join(format!("{run}-{name}"));
+        let mut n = 1;
+        while path.exists() {
+            path = dir.join(format!("{run}-{n}-{name}"));
+            n += 1;
+        }
+
+        Ok(path)
+    }
+
+    // Creates a new backup called `name` that only its owner can read, encrypted if backups are
+    pub fn create(&self, name: &str) -> color_eyre::Result<(PathBuf, BackupWriter)> {
+        let encrypt = encryption::is_enabled();
+        let path = if encrypt {
+            self.path(&format!("{name}.{}", encryption::EXTENSION))?
+        } else {
+            self.path(name)?
+        };
+
+        let file = create_private(&path)
+            .wrap_err_with(|| format!("Failed to create backup {}", path.display()))?;
+        users::apply_owner(&path);
+
+        if !encrypt {
+            return Ok((path, BackupWriter::Plain(file)));
+        }
+
+        match encryption::encrypt(file) {
+            Ok(writer) => Ok((path, BackupWriter::Encrypted(writer))),
+            Err(why) => {
+                let _ = fs::remove_file(&path);
+                Err(why)
+            }
+        }
+    }
+
+    // Adds a finished backup at `path` to the index, `source` is where a restore puts it back
+    pub fn record(
+        &self,
+        path: &Path,
+        name: &str,
+        source: Option<&Path>
+    ) -> color_eyre::Result<()> {
+        let root = store_dir()?;
+        let relative = path.strip_prefix(&root).wrap_err("Backup is outside of the store")?;
+        let size = fs::metadata(path).map(|m| m.len()).unwrap_or_default();
+
+        let mut backups = read_index(&root)?;
+        backups.push(Backup {
+            browser: self.browser.clone(),
+            installation: self.installation.clone(),
+            profile: self.profile.clone(),
+            name: name.to_owned(),
+            run: RUN.clone(),
+            path: relative.to_path_buf(),
+            source: source.map(Path::to_path_buf),
+            created: chrono::Utc::now().timestamp(),
+            size
+        });
+
+        write_index(&root, &backups)?;
+        debug!(path = %path.display(), %size, "Recorded backup");
+        Ok(())
+    }
+
+    // Copies `contents` into the store as a backup called `name`
+    pub fn save(
+        &self,
+        name: &str,
+        contents: impl AsRef<[u8]>,
+        source: Option<&Path>
+    ) -> color_eyre::Result<PathBuf> {
+        let (path, mut writer) = self.create(name)?;
+        if let Err(why) = writer.write_all(contents.as_ref()).and_then(|()| writer.finish()) {
+            let _ = fs::remove_file(&path);
+            return Err(why).wrap_err_with(|| format!("Failed to write backup {}", path.display()));
+        }
+
+        self.record(&path, name, source)?;
+        Ok(path)
+    }
+
+    // Every backed up version of the file `name`, newest first, whether it was saved on its own
+    // or as part of a profile archive
+    pub fn history(&self, name: &str) -> color_eyre::Result<Vec<Vec<u8>>> {
+        let root = store_dir()?;
+        let mut backups = indexed_backups(&root)?;
+        backups.retain(|b| {
+            b.browser == self.browser
+                && b.installation == self.installation
+                && b.profile == self.profile
+        });
+        backups.reverse();
+
+        let mut versions = Vec::new();
+        for backup in backups {
+            let path = root.join(&backup.path);
+            let contents = if archive::is_archive(&path) {
+                archive::read_file(&path, name)
+            } else if backup.name == name {
+                read_backup(&path).map(Some)
+            } else {
+                continue;
+            };
+
+            match contents {
+                Ok(Some(contents)) => versions.push(contents),
+                Ok(None) => {}
+                Err(why) => warn!(err = ?why, "Failed to read {name} from {}", path.display())
+            }
+        }
+
+        debug!(versions = %versions.len(), "Found backed up versions of {name}");
+        Ok(versions)
+    }
+}
+
+// A backup being written into the store
+pub enum BackupWriter {
+    Plain(File),
+    Encrypted(StreamWriter<File>)
+}
+
+impl BackupWriter {
+    pub fn finish(self) -> io::Result<()> {
+        let file = match self {
+            Self::Plain(file) => file,
+            Self::Encrypted(writer) => writer.finish()?
+        };
+        file.sync_all()
+    }
+}
+
+impl Write for BackupWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Encrypted(writer) => writer.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Encrypted(writer) => writer.flush()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Backup {
+    browser: String,
+    installation: String,
+    profile: String,
+    // What was backed up, backups with the same name in a slot replace each other
+    name: String,
+    run: String,
+    // Relative to the store
+    path: PathBuf,
+    // Where it was taken from, older backups and registry exports don't have one
+    source: Option<PathBuf>,
+    // Unix seconds
+    created: i64,
+    size: u64
+}
+
+impl Backup {
+    fn from_value(value: &Value) -> Option<Self> {
+        let text = |key: &str| value.get(key).and_then(Value::as_str).map(String::from);
+
+        Some(Self {
+            browser: text("browser")?,
+            installation: text("installation")?,
+            profile: text("profile")?,
+            name: text("name")?,
+            run: text("run").unwrap_or_default(),
+            path: PathBuf::from(text("path")?),
+            source: text("source").map(PathBuf::from),
+            created: value.get("created").and_then(Value::as_i64)?,
+            size: value.get("size").and_then(Value::as_u64).unwrap_or_default()
+        })
+    }
+
+    fn to_value(&self) -> Value {
+        json!({
+            "browser": self.browser,
+            "installation": self.installation,
+            "profile": self.profile,
+            "name": self.name,
+            "run": self.run,
+            "path": self.path,
+            "source": self.source,
+            "created": self.created,
+            "size": self.size
+        })
+    }
+
+    fn group(&self) -> (&str, &str, &str, &str) {
+        (&self.browser, &self.installation, &self.profile, &self.name)
+    }
+}
+
+// How many backups to keep, the newest backup of everything is always kept
+#[derive(Clone, Copy, Debug)]
+pub struct Retention {
+    // Per profile and file
+    pub keep: Option<usize>,
+    pub max_age_days: Option<u64>,
+    pub max_size_mb: Option<u64>
+}
+
+impl Retention {
+    pub fn from_args() -> Self {
+        Self {
+            keep: args().keep_backups,
+            max_age_days: args().backup_max_age,
+            max_size_mb: args().backup_max_size
+        }
+    }
+}
+
+// "local", "flatpak", "local-nightly"
+pub fn installation_key(installed_via: InstalledVia, variant: Option<Variant>) -> String {
+    variant.map_or_else(|| installed_via.to_string(), |v| format!("{installed_via}-{v}"))
+}
+
+// $XDG_STATE_HOME/clenzy/backups unless --backup-dir says otherwise
+pub fn store_dir() -> color_eyre::Result<PathBuf> {
+    if let Some(dir) = &args().backup_dir {
+        // Profiles of different users share names, so they each get their own store
+        return Ok(users::current_name().map_or_else(|| dir.clone(), |name| dir.join(name)));
+    }
+
+    users::state_dir()
+        .map(|dir| dir.join("clenzy/backups"))
+        .wrap_err("Couldn't find a directory to store backups in, set one with --backup-dir")
+}
+
+fn slug(s: &str) -> String {
+    let slug = s
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '.' | '_') { c } else { '-' })
+        .collect::<String>()
+        .to_lowercase();
+
+    // Never let a component walk out of the store
+    if slug.is_empty() || slug.chars().all(|c| c == '.') { String::from("_") } else { slug }
+}
+
+// Creates `dir` and hands every directory we created over to the user whose home it's in
+fn create_dirs(dir: &Path) -> color_eyre::Result<()> {
+    let missing =
+        dir.ancestors().take_while(|d| !d.exists()).map(Path::to_path_buf).collect::<Vec<_>>();
+
+    fs::create_dir_all(dir).wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+    for created in missing.iter().rev() {
+        // Backups hold logins and cookies, so nobody else gets to look inside the store
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(created, fs::Permissions::from_mode(0o700));
+        }
+        users::apply_owner(created);
+    }
+
+    Ok(())
+}
+
+// Creates a file only its owner can read or write
+pub fn create_private(path: &Path) -> io::Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+fn read_index(root: &Path) -> color_eyre::Result<Vec<Backup>> {
+    let path = root.join(INDEX_FILE);
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(why) => return Err(why).wrap_err("Failed to read backup index")
+    };
+
+    let index = serde_json::from_str::<Value>(&data).wrap_err("Failed to parse backup index")?;
+    let backups = index
+        .get("backups")
+        .and_then(Value::as_array)
+        .map(|backups| {
+            backups
+                .iter()
+                .filter_map(|b| {
+                    let backup = Backup::from_value(b);
+                    if backup.is_none() {
+                        warn!("Skipping malformed entry in the backup index: {b}");
+                    }
+                    backup
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(backups)
+}
+
+fn write_index(root: &Path, backups: &[Backup]) -> color_eyre::Result<()> {
+    let index = json!({
+        "version": 1,
+        "backups": backups.iter().map(Backup::to_value).collect::<Vec<_>>()
+    });
+
+    create_dirs(root)?;
+    write_atomic(&root.join(INDEX_FILE), serde_json::to_string_pretty(&index)?)
+        .wrap_err("Failed to write backup index")
+}
+
+// Indexed backups whose files still exist, oldest first
+fn indexed_backups(root: &Path) -> color_eyre::Result<Vec<Backup>> {
+    let mut backups = read_index(root)?;
+    backups.retain(|b| {
+        let exists = root.join(&b.path).exists();
+        if !exists {
+            debug!(path = %b.path.display(), "Backup was removed outside of clenzy");
+        }
+        exists
+    });
+    backups.sort_by_key(|b| b.created);
+    Ok(backups)
+}
+
+// Indices of the backups that fall outside of the retention policy
+fn expired(backups: &[Backup], retention: Retention, now: i64) -> Vec<usize> {
+    let max_age = retention.max_age_days.map(|days| i64::try_from(days).unwrap_or(i64::MAX));
+    let max_size = retention.max_size_mb.map(|mb| mb.saturating_mul(1024 * 1024));
+
+    let mut seen = HashMap::new();
+    let mut total = 0u64;
+    let mut expired = Vec::new();
+
+    // Newest first, so the ones we keep are the most recent
+    for (i, backup) in backups.iter().enumerate().rev() {
+        let count = seen.entry(backup.group()).or_insert(0usize);
+        *count += 1;
+
+        let newest = *count == 1;
+        let too_many = retention.keep.is_some_and(|keep| *count > keep);
+        let age = now.saturating_sub(backup.created);
+        let too_old = max_age.is_some_and(|days| age > days.saturating_mul(SECS_PER_DAY));
+        let too_big = max_size.is_some_and(|max| total.saturating_add(backup.size) > max);
+
+        if !newest && (too_many || too_old || too_big) {
+            expired.push(i);
+        } else {
+            total = total.saturating_add(backup.size);
+        }
+    }
+
+    expired
+}
+
+// Removes backups outside of the retention policy, returns how many and how many bytes
+pub fn prune(retention: Retention, dry_run: bool) -> color_eyre::Result<(usize, u64)> {
+    let root = store_dir()?;
+    let mut backups = indexed_backups(&root)?;
+    let expired = expired(&backups, retention, chrono::Utc::now().timestamp());
+
+    let mut removed = 0;
+    let mut freed = 0;
+    for &i in &expired {
+        let Some(backup) = backups.get(i) else {
+            continue;
+        };
+
+        let path = root.join(&backup.path);
+        if dry_run {
+            info!("Would remove {}", path.display());
+        } else if let Err(why) = remove_backup(&root, &path) {
+            warn!(err = ?why, "Failed to remove {}", path.display());
+            continue;
+        }
+
+        removed += 1;
+        freed += backup.size;
+    }
+
+    if !dry_run {
+        let mut i = 0;
+        backups.retain(|b| {
+            let keep = !expired.contains(&i) || root.join(&b.path).exists();
+            i += 1;
+            keep
+        });
+
+        if root.join(INDEX_FILE).exists() || !backups.is_empty() {
+            write_index(&root, &backups)?;
+        }
+    }
+
+    Ok((removed, freed))
+}
+
+// Applies the retention policy after a run, only warns if it can't
+pub fn enforce_retention() {
+    match prune(Retention::from_args(), false) {
+        Ok((0, _)) => debug!("No backups to prune"),
+        Ok((removed, freed)) => {
+            info!("Removed {removed} old backups, freeing {}", format_size(freed));
+        }
+        Err(why) => warn!(err = ?why, "Failed to prune old backups")
+    }
+}
+
+fn remove_backup(root: &Path, path: &Path) -> color_eyre::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+
+    // Clean up the profile and installation directories once they're empty
+    for dir in path.ancestors().skip(1).take_while(|d| *d != root) {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn list(installations: &[&Installation]) -> color_eyre::Result<()> {
+    let root = store_dir()?;
+    let backups = indexed_backups(&root)?;
+
+    if backups.is_empty() {
+        info!("No backups in {}", root.display());
+    } else {
+        info!("Backups in {}", root.display());
+    }
+
+    let mut run = None;
+    for backup in &backups {
+        if run != Some(&backup.run) {
+            let created = chrono::DateTime::from_timestamp(backup.created, 0)
+                .map(|d| d.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+
+            info!("{created} (run {})", backup.run);
+            run = Some(&backup.run);
+        }
+
+        let encrypted = if encryption::is_encrypted(&backup.path) { " (encrypted)" } else { "" };
+        info!(
+            "  {:>9}  {}/{}/{}  {}{encrypted}",
+            format_size(backup.size),
+            backup.browser,
+            backup.installation,
+            backup.profile,
+            backup.name
+        );
+    }
+
+    if !backups.is_empty() {
+        let total = backups.iter().map(|b| b.size).sum();
+        info!("{} backups, {} in total", backups.len(), format_size(total));
+    }
+
+    let legacy = legacy_backups(installations);
+    if !legacy.is_empty() {
+        let total = legacy.iter().filter_map(|p| fs::metadata(p).ok()).map(|m| m.len()).sum();
+        warn!(
+            "Found {} backups ({}) older versions left next to profiles and policies, remove \
+             them with `clenzy backups prune --legacy`",
+            legacy.len(),
+            format_size(total)
+        );
+        for path in &legacy {
+            info!("  {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+// Applies the retention policy, and removes backups older versions left next to profiles
+pub fn prune_command(
+    installations: &[&Installation],
+    legacy: bool,
+    dry_run: bool
+) -> color_eyre::Result<()> {
+    let (mut removed, mut freed) = prune(Retention::from_args(), dry_run)?;
+
+    if legacy {
+        for path in legacy_backups(installations) {
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or_default();
+            if dry_run {
+                info!("Would remove {}", path.display());
+            } else if let Err(why) = fs::remove_file(&path) {
+                warn!(err = ?why, "Failed to remove {}", path.display());
+                continue;
+            }
+
+            removed += 1;
+            freed += size;
+        }
+    }
+
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    success(&format!("{verb} {removed} backups, {}", format_size(freed)));
+    Ok(())
+}
+
+// Verifies the given archives or every archive in the store, returns whether all are intact
+pub fn verify_command(paths: &[PathBuf]) -> color_eyre::Result<bool> {
+    let paths = if paths.is_empty() {
+        let root = store_dir()?;
+        indexed_backups(&root)?
+            .into_iter()
+            .map(|b| root.join(b.path))
+            .filter(|p| archive::is_archive(p))
+            .collect()
+    } else {
+        paths.to_vec()
+    };
+
+    if paths.is_empty() {
+        info!("No backup archives to verify");
+        return Ok(true);
+    }
+
+    let mut all_intact = true;
+    for path in &paths {
+        match archive::verify(path) {
+            Ok(verified) => success(&format!(
+                "{} is intact, {} files with {}",
+                path.display(),
+                verified.files,
+                format_size(verified.size)
+            )),
+            Err(why) => {
+                all_intact = false;
+                warn!("{} failed verification: {why}", path.display());
+            }
+        }
+    }
+
+    Ok(all_intact)
+}
+
+// Puts backups back where they were taken from, archives are verified before anything is touched
+pub fn restore_command(
+    paths: &[PathBuf],
+    run: Option<&str>,
+    to: Option<&Path>
+) -> color_eyre::Result<()> {
+    let root = store_dir()?;
+    let indexed = indexed_backups(&root)?;
+
+    let mut backups = Vec::new();
+    if let Some(run) = run {
+        backups.extend(indexed.iter().filter(|b| b.run == run));
+        if backups.is_empty() {
+            bail!("No backups were made in run {run}");
+        }
+    }
+    for path in paths {
+        backups.push(find_backup(&root, &indexed, path)?);
+    }
+
+    if backups.is_empty() {
+        bail!("Nothing to restore, pass the backups to restore or --run");
+    }
+    if to.is_some() && backups.len() > 1 {
+        bail!("--to only works when restoring a single backup");
+    }
+
+    let mut failed = 0;
+    for backup in backups {
+        let path = root.join(&backup.path);
+        match restore(backup, &path, to) {
+            Ok(target) => success(&format!("Restored {} to {}", path.display(), target.display())),
+            Err(why) => {
+                failed += 1;
+                warn!(err = ?why, "Failed to restore {}", path.display());
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} backups couldn't be restored");
+    }
+
+    Ok(())
+}
+
+// Paths can be absolute, or relative to the store like `backups list` shows them
+fn find_backup<'a>(
+    root: &Path,
+    indexed: &'a [Backup],
+    path: &Path
+) -> color_eyre::Result<&'a Backup> {
+    let path = if path.exists() { path.to_path_buf() } else { root.join(path) };
+    let path = fs::canonicalize(&path)
+        .wrap_err_with(|| format!("Backup {} doesn't exist", path.display()))?;
+
+    indexed
+        .iter()
+        .find(|b| fs::canonicalize(root.join(&b.path)).is_ok_and(|p| p == path))
+        .wrap_err_with(|| format!("{} isn't in the backup index", path.display()))
+}
+
+fn restore(backup: &Backup, path: &Path, to: Option<&Path>) -> color_eyre::Result<PathBuf> {
+    let target = to
+        .map(Path::to_path_buf)
+        .or_else(|| backup.source.clone())
+        .wrap_err("Backup doesn't record where it was taken from, pass --to")?;
+
+    if !ensure_closed(backup, &target) {
+        bail!("{} is in use", target.display());
+    }
+
+    if archive::is_archive(path) {
+        let verified = archive::verify(path).wrap_err("Backup failed verification")?;
+        debug!(files = %verified.files, "Verified backup before restoring");
+        archive::extract(path, &target)?;
+        return Ok(target);
+    }
+
+    let contents = read_backup(path)?;
+
+    // Policies live in folders only root can write to
+    #[cfg(target_os = "linux")]
+    if backup.profile == "policies" {
+        let contents = String::from_utf8(contents).wrap_err("Policy backup isn't text")?;
+        let files = [crate::util::elevate::PrivilegedWrite::new(&target, contents)];
+        crate::util::elevate::write_or_elevate("Restoring backups", &files)?;
+        return Ok(target);
+    }
+
+    write_atomic(&target, contents)
+        .wrap_err_with(|| format!("Failed to write {}", target.display()))?;
+    Ok(target)
+}
+
+// A single file backup, decrypted if it was encrypted
+fn read_backup(path: &Path) -> color_eyre::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    let file = File::open(path).wrap_err("Failed to open backup")?;
+    if encryption::is_encrypted(path) {
+        encryption::decrypt(file)?.read_to_end(&mut contents)
+    } else {
+        io::BufReader::new(file).read_to_end(&mut contents)
+    }
+    .wrap_err("Failed to read backup")?;
+
+    Ok(contents)
+}
+
+// Brave rewrites its data folder and Firefox its profile on exit, which would undo a restore
+fn ensure_closed(backup: &Backup, target: &Path) -> bool {
+    if backup.profile == "policies" {
+        return true;
+    }
+
+    // Archives are whole profiles, anything else is a file inside one
+    let dir = if archive::is_archive(&backup.path) { Some(target) } else { target.parent() };
+    let Some(dir) = dir else {
+        return true;
+    };
+
+    if backup.browser == slug(Brave::name()) {
+        // Files are in a profile or in the data folder itself, and the data folder holds the lock
+        [Some(dir), dir.parent()]
+            .into_iter()
+            .flatten()
+            .all(|dir| ensure_unlocked(LockKind::Chromium, dir, &dir.display()))
+    } else {
+        ensure_unlocked(LockKind::Firefox, dir, &dir.display())
+    }
+}
+
+// Backups older versions wrote into data folders, profiles and Brave's policy folder, like
+// <profile>-backup-<ts>.zip
+fn legacy_backups(installations: &[&Installation]) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for data_folder in installations.iter().flat_map(|i| &i.data_folders) {
+        let Ok(entries) = fs::read_dir(data_folder) else {
+            continue;
+        };
+
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.is_dir() {
+                // Brave keeps Preferences backups inside each profile
+                let Ok(children) = fs::read_dir(&path) else {
+                    continue;
+                };
+
+                let children = children.filter_map(Result::ok).map(|c| c.path());
+                found.extend(children.filter(|p| is_legacy(p)));
+            } else if is_legacy(&path) {
+                found.push(path);
+            }
+        }
+    }
+
+    // Brave's policies were backed up next to them, where Brave still reads them as policies
+    let brave = installations
+        .iter()
+        .any(|i| i.browser_name == Brave::name() && i.installed_via == InstalledVia::Local);
+    if cfg!(target_os = "linux")
+        && brave
+        && let Ok(entries) = fs::read_dir(LEGACY_BRAVE_POLICIES)
+    {
+        found.extend(entries.filter_map(Result::ok).map(|e| e.path()).filter(|p| is_legacy(p)));
+    }
+
+    found.sort();
+    found.dedup();
+    found
+}
+
+fn is_legacy(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let Some((stem, extension)) = name.rsplit_once('.') else {
+        return false;
+    };
+    let Some((prefix, stamp)) = stem.rsplit_once('-') else {
+        return false;
+    };
+
+    // Every old backup ended with a 202501192003 style timestamp
+    let is_timestamp = stamp.len() == 12 && stamp.chars().all(|c| c.is_ascii_digit());
+    let is_backup = match extension {
+        "zip" | "reg" => prefix.ends_with("-backup") || prefix == "policies-backup",
+        "bak" => matches!(prefix, "Preferences" | "ChromeFeatureState"),
+        "plist" => prefix.starts_with("com.brave.Browser"),
+        "json" => prefix == "policies",
+        _ => false
+    };
+
+    path.is_file() && is_timestamp && is_backup
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut units = UNITS.iter();
+    while size >= 1024.0 && units.len() > 1 {
+        size /= 1024.0;
+        units.next();
+    }
+
+    match units.next() {
+        Some(&unit) if unit != "B" => format!("{size:.1} {unit}"),
+        _ => format!("{bytes} B")
+    }
+}
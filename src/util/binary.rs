@@ -0,0 +1,34 @@
+use aho_corasick::AhoCorasick;
+use color_eyre::eyre::WrapErr;
+use std::{collections::HashSet, io::Read};
+
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+// Finds which of `patterns` appear in `data`, reading it in chunks so big binaries never sit in
+// memory, and adds their indices to `found`
+pub fn find_patterns(
+    data: &mut dyn Read,
+    searcher: &AhoCorasick,
+    longest: usize,
+    found: &mut HashSet<usize>
+) -> color_eyre::Result<()> {
+    let mut buffer = Vec::new();
+    loop {
+        let read = data.take(CHUNK_SIZE).read_to_end(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        found.extend(searcher.find_overlapping_iter(&buffer).map(|m| m.pattern().as_usize()));
+        // Keep enough of this chunk to find a pattern split across it and the next one
+        buffer.drain(..buffer.len().saturating_sub(longest));
+    }
+
+    Ok(())
+}
+
+// Patterns are searched for as written, so callers add whatever terminates them
+pub fn searcher(patterns: &[String]) -> color_eyre::Result<(AhoCorasick, usize)> {
+    let searcher = AhoCorasick::new(patterns).wrap_err("Failed to build string searcher")?;
+    Ok((searcher, patterns.iter().map(String::len).max().unwrap_or_default()))
+}
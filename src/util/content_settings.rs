@@ -0,0 +1,454 @@
+use crate::{
+    ContentKind, Permission, firefox::common::prefs::{Pref, PrefValue}, s, util::{args, backups::create_private, get_or_insert_obj, transaction::Transaction}
+};
+use clap::ValueEnum;
+use color_eyre::eyre::{bail, eyre, WrapErr};
+use rusqlite::{Connection, OpenFlags, params};
+use serde_json::{Map, Value, json};
+use std::{
+    collections::BTreeMap, fs, path::{Path, PathBuf}, process, str::FromStr
+};
+use tracing::debug;
+
+// Microseconds between 1601, where Chromium's clock starts, and 1970
+const CHROMIUM_EPOCH_OFFSET: i64 = 11_644_473_600_000_000;
+
+// What clenzy sets when nothing else was asked for: no notification prompts, but sites can still
+// ask for the location
+const DEFAULTS: [(ContentKind, Permission); 2] =
+    [(ContentKind::Notifications, Permission::Block), (ContentKind::Geolocation, Permission::Ask)];
+
+const KINDS: [ContentKind; 5] = [
+    ContentKind::Notifications,
+    ContentKind::Geolocation,
+    ContentKind::CameraMic,
+    ContentKind::Autoplay,
+    ContentKind::Popups
+];
+
+// A permission for one site, overriding the default
+#[derive(Clone, Debug)]
+pub struct SiteException {
+    pub kind: ContentKind,
+    pub permission: Permission,
+    pub origin: String
+}
+
+impl FromStr for SiteException {
+    type Err = color_eyre::Report;
+
+    // notifications:allow:https://example.com
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, rest) = s.split_once(':').ok_or_else(|| eyre!("Missing the setting in {s}"))?;
+        let (permission, origin) =
+            rest.split_once(':').ok_or_else(|| eyre!("Missing the site in {s}"))?;
+
+        let kind = ContentKind::from_str(kind, true).map_err(|why| eyre!(why))?;
+        let permission = Permission::from_str(permission, true).map_err(|why| eyre!(why))?;
+
+        Ok(Self { kind, permission, origin: parse_origin(origin)? })
+    }
+}
+
+pub fn parse_origin(origin: &str) -> color_eyre::Result<String> {
+    let origin = origin.trim().trim_end_matches('/');
+    if !origin.contains("://") {
+        bail!("{origin} isn't an origin, like https://example.com");
+    }
+
+    Ok(origin.to_owned())
+}
+
+fn explicit(kind: ContentKind) -> Option<Permission> {
+    let args = args();
+    match kind {
+        ContentKind::Notifications => args.notifications,
+        ContentKind::Geolocation => args.geolocation,
+        ContentKind::CameraMic => args.camera_mic,
+        ContentKind::Autoplay => args.autoplay,
+        ContentKind::Popups => args.popups
+    }
+}
+
+// Every default to set in prefs, with ours filling in what wasn't passed
+fn defaults() -> Vec<(ContentKind, Permission)> {
+    KINDS
+        .into_iter()
+        .filter_map(|kind| {
+            let ours = DEFAULTS.iter().find(|(k, _)| *k == kind).map(|(_, p)| *p);
+            Some((kind, explicit(kind).or(ours)?))
+        })
+        .collect()
+}
+
+// Only what was passed, since policies take the setting away from the user
+fn explicit_defaults() -> Vec<(ContentKind, Permission)> {
+    KINDS.into_iter().filter_map(|kind| Some((kind, explicit(kind)?))).collect()
+}
+
+pub fn exceptions() -> color_eyre::Result<Vec<SiteException>> {
+    args()
+        .site_permissions
+        .iter()
+        .map(|s| s.parse().wrap_err_with(|| format!("Invalid site permission {s}")))
+        .collect()
+}
+
+// Invalid ones already stopped clenzy in main before anything was written
+fn site_exceptions() -> Vec<SiteException> {
+    exceptions().unwrap_or_default()
+}
+
+// The user.js lines for our defaults, for Firefox and Zen
+pub fn firefox_user_js() -> String {
+    let mut lines = vec![String::from("// Default permissions of sites, set by clenzy")];
+    for (kind, permission) in defaults() {
+        for (name, value) in firefox_prefs(kind, permission) {
+            lines.push(Pref { name: name.to_owned(), value }.to_user_pref());
+        }
+    }
+
+    lines.join("\n")
+}
+
+fn firefox_prefs(kind: ContentKind, permission: Permission) -> Vec<(&'static str, PrefValue)> {
+    // permissions.default.* use 0 for ask, 1 for allow and 2 for block
+    let default = PrefValue::Int(match permission {
+        Permission::Ask => 0,
+        Permission::Allow => 1,
+        Permission::Block => 2
+    });
+
+    match kind {
+        ContentKind::Notifications => vec![("permissions.default.desktop-notification", default)],
+        ContentKind::Geolocation => vec![("permissions.default.geo", default)],
+        ContentKind::CameraMic => vec![
+            ("permissions.default.camera", default.clone()),
+            ("permissions.default.microphone", default)
+        ],
+        // Blocking audible autoplay is the closest Firefox has to asking
+        ContentKind::Autoplay => vec![(
+            "media.autoplay.default",
+            PrefValue::Int(match permission {
+                Permission::Allow => 0,
+                Permission::Ask => 1,
+                Permission::Block => 5
+            })
+        )],
+        ContentKind::Popups => {
+            vec![("dom.disable_open_during_load", PrefValue::Bool(permission != Permission::Allow))]
+        }
+    }
+}
+
+// Names in Firefox's permission manager
+const fn firefox_types(kind: ContentKind) -> &'static [&'static str] {
+    match kind {
+        ContentKind::Notifications => &["desktop-notification"],
+        ContentKind::Geolocation => &["geo"],
+        ContentKind::CameraMic => &["camera", "microphone"],
+        ContentKind::Autoplay => &["autoplay-media"],
+        ContentKind::Popups => &["popup"]
+    }
+}
+
+// The Permissions and PopupBlocking policies, only for what was passed
+pub fn firefox_policies(locked: bool) -> Map<String, Value> {
+    let exceptions = site_exceptions();
+    let sites = |kind: ContentKind, permission: Permission| {
+        exceptions
+            .iter()
+            .filter(|e| e.kind == kind && e.permission == permission)
+            .map(|e| json!(e.origin))
+            .collect::<Vec<_>>()
+    };
+
+    let mut permissions = Map::new();
+    for kind in KINDS {
+        let default = explicit(kind);
+        let allow = sites(kind, Permission::Allow);
+        let block = sites(kind, Permission::Block);
+        if default.is_none() && allow.is_empty() && block.is_empty() {
+            continue;
+        }
+
+        let mut policy = Map::new();
+        if !allow.is_empty() {
+            policy.insert(s!("Allow"), Value::Array(allow));
+        }
+        if !block.is_empty() && kind != ContentKind::Popups {
+            policy.insert(s!("Block"), Value::Array(block));
+        }
+
+        match (kind, default) {
+            (ContentKind::Autoplay, Some(default)) => {
+                let default = match default {
+                    Permission::Allow => "allow-audio-video",
+                    Permission::Ask => "block-audio",
+                    Permission::Block => "block-audio-video"
+                };
+                policy.insert(s!("Default"), json!(default));
+            }
+            (ContentKind::Popups, Some(default)) => {
+                policy.insert(s!("Default"), json!(default != Permission::Allow));
+            }
+            // Firefox can't allow these everywhere through policies, only stop asking
+            (_, Some(default)) => {
+                policy.insert(s!("BlockNewRequests"), json!(default == Permission::Block));
+            }
+            (_, None) => {}
+        }
+        policy.insert(s!("Locked"), json!(locked));
+
+        let name = match kind {
+            ContentKind::Notifications => "Notifications",
+            ContentKind::Geolocation => "Location",
+            ContentKind::CameraMic => {
+                permissions.insert(s!("Microphone"), Value::Object(policy.clone()));
+                "Camera"
+            }
+            ContentKind::Autoplay => "Autoplay",
+            ContentKind::Popups => "PopupBlocking"
+        };
+        permissions.insert(name.to_owned(), Value::Object(policy));
+    }
+
+    let mut policies = Map::new();
+    if let Some(popups) = permissions.remove("PopupBlocking") {
+        policies.insert(s!("PopupBlocking"), popups);
+    }
+    if !permissions.is_empty() {
+        policies.insert(s!("Permissions"), Value::Object(permissions));
+    }
+
+    policies
+}
+
+// Stages the site exceptions in permissions.sqlite, Firefox has to be closed
+pub fn firefox_site_permissions(
+    profile: &Path,
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
+    let mut permissions = Vec::new();
+    for exception in site_exceptions() {
+        // Asking is what happens without an entry
+        let permission = match exception.permission {
+            Permission::Ask => None,
+            Permission::Allow => Some(1),
+            Permission::Block => Some(2)
+        };
+
+        for kind in firefox_types(exception.kind) {
+            permissions.push((exception.origin.clone(), *kind, permission));
+        }
+    }
+
+    set_firefox_permissions(profile, &permissions, transaction)
+}
+
+// Origin, type and permission for each entry, None removes it. The database is edited as a copy
+// that's staged in `transaction`, so it's only replaced together with everything else.
+pub fn set_firefox_permissions(
+    profile: &Path,
+    permissions: &[(String, &str, Option<u32>)],
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
+    if permissions.is_empty() {
+        return Ok(());
+    }
+
+    // Firefox creates it with its own schema on first start, so don't make one up
+    let path = profile.join("permissions.sqlite");
+    if !path.exists() {
+        debug!("No permissions.sqlite yet, not setting site permissions");
+        return Ok(());
+    }
+
+    // Start from what's already staged, so earlier changes in this run aren't lost
+    let source = transaction.staged(&path).unwrap_or(&path).to_path_buf();
+    // Next to the database like the transaction's temp files, and only readable by its owner
+    let copy = profile.join(format!(".permissions.sqlite.clenzy-{}.tmp", process::id()));
+    let _ = fs::remove_file(&copy);
+
+    let result = create_private(&copy)
+        .wrap_err("Failed to create a working copy")
+        .and_then(|_| copy_database(&source, &copy))
+        .and_then(|()| edit_permissions(&copy, permissions))
+        .and_then(|()| fs::read(&copy).wrap_err("Failed to read the edited copy"));
+    let _ = fs::remove_file(&copy);
+    let database = result.wrap_err_with(|| format!("Failed to edit {}", path.display()))?;
+
+    transaction.write(&path, database)?;
+    // The copy already has everything in the write-ahead log, which would be replayed over it
+    for suffix in ["-wal", "-shm"] {
+        let mut name = path.clone().into_os_string();
+        name.push(suffix);
+        let sidecar = PathBuf::from(name);
+        if sidecar.exists() {
+            transaction.remove(&sidecar)?;
+        }
+    }
+
+    debug!("Staged {} site permissions", permissions.len());
+    Ok(())
+}
+
+// A standalone copy with the write-ahead log folded in
+fn copy_database(source: &Path, copy: &Path) -> color_eyre::Result<()> {
+    let conn = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .wrap_err_with(|| format!("Failed to open {}", source.display()))?;
+    conn.execute("VACUUM INTO ?1", params![copy.to_string_lossy()])
+        .wrap_err("Failed to copy the database")?;
+    Ok(())
+}
+
+fn edit_permissions(
+    database: &Path,
+    permissions: &[(String, &str, Option<u32>)]
+) -> color_eyre::Result<()> {
+    let mut conn = Connection::open(database)?;
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let transaction = conn.transaction()?;
+    for (origin, kind, permission) in permissions {
+        transaction.execute(
+            "DELETE FROM moz_perms WHERE origin = ?1 AND type = ?2",
+            params![origin, kind]
+        )?;
+
+        if let Some(permission) = permission {
+            transaction.execute(
+                "INSERT INTO moz_perms \
+                 (origin, type, permission, expireType, expireTime, modificationTime) \
+                 VALUES (?1, ?2, ?3, 0, 0, ?4)",
+                params![origin, kind, permission, now]
+            )?;
+        }
+    }
+    transaction.commit().wrap_err("Failed to write site permissions")?;
+    Ok(())
+}
+
+// Keys under profile.default_content_setting_values and profile.content_settings.exceptions
+const fn chromium_keys(kind: ContentKind) -> &'static [&'static str] {
+    match kind {
+        ContentKind::Notifications => &["notifications"],
+        ContentKind::Geolocation => &["geolocation"],
+        ContentKind::CameraMic => &["media_stream_camera", "media_stream_mic"],
+        // Brave's own setting, Chromium only has sound
+        ContentKind::Autoplay => &["autoplay"],
+        ContentKind::Popups => &["popups"]
+    }
+}
+
+// Chromium's ContentSetting, autoplay and popups can't ask so they're blocked like by default
+const fn chromium_value(kind: ContentKind, permission: Permission) -> u32 {
+    match (kind, permission) {
+        (_, Permission::Allow) => 1,
+        (ContentKind::Autoplay | ContentKind::Popups, Permission::Ask)
+        | (_, Permission::Block) => 2,
+        (_, Permission::Ask) => 3
+    }
+}
+
+// Defaults and site exceptions in a Brave profile's Preferences
+pub fn apply_chromium_preferences(prefs: &mut Map<String, Value>) -> color_eyre::Result<()> {
+    let exceptions = site_exceptions();
+    let Some(profile) = get_or_insert_obj(prefs, "profile") else {
+        bail!("Failed to get profile object");
+    };
+
+    if let Some(values) = get_or_insert_obj(profile, "default_content_setting_values") {
+        for (kind, permission) in defaults() {
+            for key in chromium_keys(kind) {
+                values.insert((*key).to_owned(), json!(chromium_value(kind, permission)));
+            }
+        }
+    }
+
+    for exception in &exceptions {
+        for key in chromium_keys(exception.kind) {
+            let setting = chromium_value(exception.kind, exception.permission);
+            insert_chromium_exception(prefs, key, &exception.origin, setting)?;
+        }
+    }
+
+    Ok(())
+}
+
+// An exception under profile.content_settings.exceptions, for the site wherever it's embedded
+pub fn insert_chromium_exception(
+    prefs: &mut Map<String, Value>,
+    key: &str,
+    origin: &str,
+    setting: u32
+) -> color_eyre::Result<()> {
+    let Some(sites) = get_or_insert_obj(prefs, "profile")
+        .and_then(|p| get_or_insert_obj(p, "content_settings"))
+        .and_then(|c| get_or_insert_obj(c, "exceptions"))
+        .and_then(|e| get_or_insert_obj(e, key))
+    else {
+        bail!("Failed to get content settings exceptions for {key}");
+    };
+
+    let last_modified =
+        (chrono::Utc::now().timestamp_micros() + CHROMIUM_EPOCH_OFFSET).to_string();
+    sites.insert(
+        format!("{origin},*"),
+        json!({ "setting": setting, "last_modified": last_modified })
+    );
+    Ok(())
+}
+
+// Default*Setting and the per site policies, only for what was passed
+pub fn chromium_policies() -> Map<String, Value> {
+    let mut policies = Map::new();
+    for (kind, permission) in explicit_defaults() {
+        let value = chromium_value(kind, permission);
+        match kind {
+            ContentKind::Notifications => {
+                policies.insert(s!("DefaultNotificationsSetting"), json!(value));
+            }
+            ContentKind::Geolocation => {
+                policies.insert(s!("DefaultGeolocationSetting"), json!(value));
+            }
+            // The policy can only block or ask
+            ContentKind::CameraMic if permission != Permission::Allow => {
+                policies.insert(s!("DefaultMediaStreamSetting"), json!(value));
+            }
+            ContentKind::CameraMic => debug!("Camera and microphone can't be allowed by policy"),
+            ContentKind::Autoplay => {
+                policies.insert(s!("AutoplayAllowed"), json!(permission == Permission::Allow));
+            }
+            ContentKind::Popups => {
+                policies.insert(s!("DefaultPopupsSetting"), json!(value));
+            }
+        }
+    }
+
+    let mut lists = BTreeMap::new();
+    for exception in site_exceptions() {
+        let names: &[&str] = match (exception.kind, exception.permission) {
+            (ContentKind::Notifications, Permission::Allow) => &["NotificationsAllowedForUrls"],
+            (ContentKind::Notifications, Permission::Block) => &["NotificationsBlockedForUrls"],
+            (ContentKind::CameraMic, Permission::Allow) => {
+                &["VideoCaptureAllowedUrls", "AudioCaptureAllowedUrls"]
+            }
+            (ContentKind::Autoplay, Permission::Allow) => &["AutoplayAllowlist"],
+            (ContentKind::Popups, Permission::Allow) => &["PopupsAllowedForUrls"],
+            (ContentKind::Popups, Permission::Block) => &["PopupsBlockedForUrls"],
+            _ => {
+                debug!("No policy for {:?} {:?} exceptions", exception.kind, exception.permission);
+                &[]
+            }
+        };
+
+        for name in names {
+            let list = lists.entry(*name).or_insert_with(Vec::new);
+            list.push(json!(exception.origin));
+        }
+    }
+
+    policies.extend(lists.into_iter().map(|(name, list)| (name.to_owned(), Value::Array(list))));
+    policies
+}
@@ -0,0 +1,207 @@
+use crate::util::transaction::Transaction;
+#[cfg(target_os = "linux")]
+use crate::{
+    Elevator, util::{args, is_permission_denied, should_elevate, users}
+};
+#[cfg(target_os = "linux")]
+use clap::ValueEnum;
+#[cfg(target_os = "linux")]
+use color_eyre::eyre::{ContextCompat, bail};
+use color_eyre::eyre::WrapErr;
+#[cfg(target_os = "linux")]
+use serde_json::{Value, json};
+use std::{fs, path::PathBuf};
+#[cfg(target_os = "linux")]
+use std::{
+    env, io::{IsTerminal, Read, Write}, path::{Component, Path}, process::{Command, Stdio}
+};
+#[cfg(target_os = "linux")]
+use tracing::{debug, info};
+
+// The helper refuses to write anywhere browsers don't read system wide config from, app folders
+// are limited to the browsers' own
+#[cfg(target_os = "linux")]
+const ALLOWED_ROOTS: [&str; 7] = [
+    "/etc/firefox",
+    "/etc/brave",
+    "/var/lib/flatpak/extension",
+    "/opt/brave.com",
+    "/usr/lib/firefox",
+    "/usr/lib64/firefox",
+    "/usr/lib/firefox-esr"
+];
+
+// A file the privileged helper writes, computed up front by the unprivileged parent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrivilegedWrite {
+    pub path: PathBuf,
+    pub contents: String
+}
+
+impl PrivilegedWrite {
+    pub fn new(path: impl Into<PathBuf>, contents: impl Into<String>) -> Self {
+        Self { path: path.into(), contents: contents.into() }
+    }
+}
+
+#[cfg(target_os = "linux")]
+// Writes every file in one transaction, falling back to the privileged helper if we aren't allowed
+pub fn write_or_elevate(action: &str, files: &[PrivilegedWrite]) -> color_eyre::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    match write_all(files) {
+        Ok(()) => Ok(()),
+        Err(why) if is_permission_denied(&why) => {
+            debug!(err = ?why, "Writing needs elevated permissions");
+            write_elevated(action, files).map(|_| ())
+        }
+        Err(why) => Err(why)
+    }
+}
+
+#[cfg(target_os = "linux")]
+// Hands the files to the privileged helper, returns false if the user didn't want to elevate
+pub fn write_elevated(action: &str, files: &[PrivilegedWrite]) -> color_eyre::Result<bool> {
+    if !should_elevate(action) {
+        return Ok(false);
+    }
+
+    let elevator = elevator()?;
+    let exe = env::current_exe().wrap_err("Failed to resolve current exe")?;
+    let tool = name(elevator);
+    debug!(%tool, len = %files.len(), "Starting privileged helper");
+
+    let mut child = Command::new(&tool)
+        .arg(&exe)
+        .arg("--privileged-helper")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .wrap_err_with(|| format!("Failed to run {tool}"))?;
+
+    let request = json!({
+        "files": files
+            .iter()
+            .map(|f| json!({ "path": f.path, "contents": f.contents }))
+            .collect::<Vec<_>>()
+    });
+
+    // The helper may exit before reading anything if authentication fails, the status says why
+    let sent = child
+        .stdin
+        .take()
+        .wrap_err("Helper has no stdin")
+        .and_then(|mut stdin| Ok(stdin.write_all(serde_json::to_string(&request)?.as_bytes())?));
+
+    let status = child.wait().wrap_err("Failed to wait for privileged helper")?;
+    if !status.success() {
+        bail!("{tool} exited with {status}");
+    }
+
+    sent.wrap_err("Failed to send files to privileged helper")?;
+    Ok(true)
+}
+
+// Writes every file or none of them
+pub fn write_all(files: &[PrivilegedWrite]) -> color_eyre::Result<()> {
+    let mut transaction = Transaction::new();
+    for file in files {
+        if let Some(parent) = file.path.parent() {
+            fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        transaction
+            .write(&file.path, &file.contents)
+            .wrap_err_with(|| format!("Failed to write {}", file.path.display()))?;
+    }
+
+    transaction.commit()
+}
+
+#[cfg(target_os = "linux")]
+fn name(elevator: Elevator) -> String {
+    elevator.to_possible_value().map(|v| v.get_name().to_owned()).unwrap_or_default()
+}
+
+#[cfg(target_os = "linux")]
+fn is_installed(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+#[cfg(target_os = "linux")]
+fn elevator() -> color_eyre::Result<Elevator> {
+    if let Some(elevator) = args().elevate_with {
+        return Ok(elevator);
+    }
+
+    let mut available = Elevator::value_variants()
+        .iter()
+        .copied()
+        .filter(|e| is_installed(&name(*e)))
+        .collect::<Vec<_>>();
+
+    // Without a terminal to type a password into, only pkexec can ask for one
+    if !std::io::stdin().is_terminal() {
+        available.sort_by_key(|e| *e != Elevator::Pkexec);
+    }
+
+    match available.as_slice() {
+        [] => bail!("Found none of sudo, doas, pkexec or run0, run clenzy as root instead"),
+        [only] => Ok(*only),
+        [first, ..] if args().auto_confirm => Ok(*first),
+        _ => {
+            let names = available.iter().map(|e| name(*e)).collect::<Vec<_>>();
+            let chosen = inquire::Select::new("Elevate with", names.clone()).prompt()?;
+            let position = names.iter().position(|n| *n == chosen).unwrap_or_default();
+            available.get(position).copied().wrap_err("Selected an unknown elevation tool")
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+// Runs as root. Reads the files to write from stdin and does nothing else.
+pub fn run_helper() -> color_eyre::Result<()> {
+    if !users::is_root() {
+        bail!("The privileged helper has to run as root");
+    }
+
+    let mut request = String::new();
+    std::io::stdin().read_to_string(&mut request).wrap_err("Failed to read request")?;
+    let request = serde_json::from_str::<Value>(&request).wrap_err("Failed to parse request")?;
+
+    let files = request
+        .get("files")
+        .and_then(Value::as_array)
+        .wrap_err("Request has no files")?
+        .iter()
+        .map(|file| {
+            let path = file.get("path").and_then(Value::as_str).wrap_err("File has no path")?;
+            let contents =
+                file.get("contents").and_then(Value::as_str).wrap_err("File has no contents")?;
+            Ok(PrivilegedWrite::new(path, contents))
+        })
+        .collect::<color_eyre::Result<Vec<_>>>()?;
+
+    // Check everything before writing anything
+    for file in &files {
+        if !is_allowed(&file.path) {
+            bail!("Refusing to write {}", file.path.display());
+        }
+    }
+
+    write_all(&files)?;
+    info!("Wrote {} files with elevated permissions", files.len());
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn is_allowed(path: &Path) -> bool {
+    path.is_absolute()
+        && path.components().all(|c| !matches!(c, Component::ParentDir))
+        && ALLOWED_ROOTS.iter().any(|root| path.starts_with(root))
+}
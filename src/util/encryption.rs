@@ -0,0 +1,102 @@
+use crate::util::args;
+use age::{secrecy::SecretString, stream::{StreamReader, StreamWriter}};
+use color_eyre::eyre::{bail, eyre, ContextCompat, WrapErr};
+use std::{
+    env, fs, io::{BufReader, Read, Write}, path::Path, sync::OnceLock
+};
+use tracing::debug;
+
+// Lets unattended runs encrypt without a passphrase file on disk
+const PASSPHRASE_ENV: &str = "CLENZY_BACKUP_PASSPHRASE";
+// Encrypted backups are age files, so `age -d` can open them without clenzy
+pub const EXTENSION: &str = "age";
+
+static PASSPHRASE: OnceLock<SecretString> = OnceLock::new();
+
+// Profiles hold logins, keys and cookies, so backups of them can be encrypted
+pub fn is_enabled() -> bool {
+    args().encrypt_backups || !args().backup_recipients.is_empty()
+}
+
+pub fn is_encrypted(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == EXTENSION)
+}
+
+// Encrypts to the recipients if there are any, otherwise with the passphrase
+pub fn encrypt<W: Write>(output: W) -> color_eyre::Result<StreamWriter<W>> {
+    let encryptor = if args().backup_recipients.is_empty() {
+        age::Encryptor::with_user_passphrase(passphrase(true)?.clone())
+    } else {
+        let recipients = args()
+            .backup_recipients
+            .iter()
+            .map(|r| {
+                r.parse::<age::x25519::Recipient>()
+                    .map_err(|why| eyre!("Invalid backup recipient {r}: {why}"))
+            })
+            .collect::<color_eyre::Result<Vec<_>>>()?;
+
+        age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+            .wrap_err("Failed to set up backup encryption")?
+    };
+
+    encryptor.wrap_output(output).wrap_err("Failed to start encrypting backup")
+}
+
+// Backups made with a passphrase ask for it, ones made for recipients need --backup-identity
+pub fn decrypt<R: Read>(input: R) -> color_eyre::Result<StreamReader<R>> {
+    let decryptor = age::Decryptor::new(input).wrap_err("Not an encrypted backup")?;
+
+    let reader = if decryptor.is_scrypt() {
+        let identity = age::scrypt::Identity::new(passphrase(false)?.clone());
+        decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity))
+    } else {
+        let path = args()
+            .backup_identity
+            .as_ref()
+            .wrap_err("Backup was encrypted for a key, pass its identity with --backup-identity")?;
+        let file = fs::File::open(path)
+            .wrap_err_with(|| format!("Failed to open identity file {}", path.display()))?;
+        let identities = age::IdentityFile::from_buffer(BufReader::new(file))
+            .wrap_err("Failed to read identity file")?
+            .into_identities()
+            .wrap_err("Failed to parse identity file")?;
+
+        decryptor.decrypt(identities.iter().map(|i| &**i as &dyn age::Identity))
+    };
+
+    reader.wrap_err("Failed to decrypt backup, wrong passphrase or identity?")
+}
+
+// Taken from --backup-passphrase-file, the environment, then a prompt, and asked for once per run
+fn passphrase(confirm: bool) -> color_eyre::Result<&'static SecretString> {
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase);
+    }
+
+    let passphrase = if let Some(path) = &args().backup_passphrase_file {
+        debug!("Reading backup passphrase from {}", path.display());
+        let contents = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read passphrase file {}", path.display()))?;
+        contents.lines().next().unwrap_or_default().to_owned()
+    } else if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+        debug!("Using backup passphrase from {PASSPHRASE_ENV}");
+        passphrase
+    } else if args().auto_confirm {
+        bail!(
+            "Encrypted backups need a passphrase, set {PASSPHRASE_ENV} or pass \
+             --backup-passphrase-file when using -Y"
+        );
+    } else {
+        let prompt = inquire::Password::new("Backup passphrase:")
+            .with_display_mode(inquire::PasswordDisplayMode::Masked);
+        let prompt = if confirm { prompt } else { prompt.without_confirmation() };
+        prompt.prompt().wrap_err("Failed to read backup passphrase")?
+    };
+
+    if passphrase.is_empty() {
+        bail!("Backup passphrase is empty");
+    }
+
+    Ok(PASSPHRASE.get_or_init(|| SecretString::from(passphrase)))
+}
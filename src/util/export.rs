@@ -0,0 +1,235 @@
+use crate::{
+    brave, browser::installation::InstalledVia, firefox::{self, common::css}, s, util::{args, logging::success, transaction::write_atomic}, zen
+};
+use clap::ValueEnum;
+use color_eyre::eyre::WrapErr;
+use serde_json::{Map, Value, json};
+use std::{fs, path::Path};
+use tracing::debug;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const PACKAGINGS: [InstalledVia; 3] =
+    [InstalledVia::Local, InstalledVia::Snap, InstalledVia::Flatpak];
+const FIREFOX_DATA_FOLDERS: [&str; 3] = [
+    "~/.mozilla/firefox",
+    "~/snap/firefox/common/.mozilla/firefox",
+    "~/.var/app/org.mozilla.firefox/.mozilla/firefox"
+];
+const ZEN_DATA_FOLDERS: [&str; 3] =
+    ["~/.zen", "~/snap/0xgingi-zen-browser/common/.zen", "~/.var/app/app.zen_browser.zen/.zen"];
+// What clenzy writes that depends on what's already in a profile, so it can't be exported
+const NOT_EXPORTED: [&str; 3] = [
+    "the Firefox toolbar state, built from each profile's layout and installed extensions",
+    "prefs.js with --firefox-mode prefsjs, user.js has the same prefs to merge into it",
+    "site permissions and kept cookies, written into each profile's permissions.sqlite"
+];
+
+struct Artifact {
+    // Relative to the export directory
+    path: String,
+    browser: &'static str,
+    description: &'static str,
+    // The options this file was built with, when there's one file for each of them
+    options: Map<String, Value>,
+    contents: String,
+    // Where the file goes on a Linux host for each packaging, None if that packaging can't use it
+    destinations: Vec<(InstalledVia, Option<String>)>
+}
+
+// Writes everything clenzy would apply with the current options into `out`, along with a manifest
+// of where each file belongs. Nothing outside of `out` is touched.
+pub fn export(out: &Path) -> color_eyre::Result<()> {
+    let artifacts = artifacts()?;
+
+    for artifact in &artifacts {
+        let path = out.join(&artifact.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        write_atomic(&path, &artifact.contents)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+        debug!(path = %path.display(), "Exported {}", artifact.description);
+    }
+
+    let manifest_path = out.join(MANIFEST_FILE);
+    write_atomic(&manifest_path, serde_json::to_string_pretty(&manifest(&artifacts))?)
+        .wrap_err_with(|| format!("Failed to write {}", manifest_path.display()))?;
+
+    success(&format!("Exported {} files to {}", artifacts.len(), out.display()));
+    Ok(())
+}
+
+fn artifacts() -> color_eyre::Result<Vec<Artifact>> {
+    let enforcement = args().enforcement;
+
+    let mut firefox_policies = Map::new();
+    firefox_policies.insert(s!("policies"), Value::Object(firefox::validated_policies()?));
+
+    let initial_preferences = brave::initial_preferences::generate(None)?;
+    let mut feature_state = Map::new();
+    brave::apply_feature_state(&mut feature_state, None);
+
+    let mut artifacts = Vec::new();
+    for vertical_tabs in [true, false] {
+        for search_suggestions in [false, true] {
+            let options = Map::from_iter([
+                (s!("vertical_tabs"), json!(vertical_tabs)),
+                (s!("search_suggestions"), json!(search_suggestions))
+            ]);
+            artifacts.push(Artifact {
+                path: format!("firefox/{}/user.js", option_set(&options)),
+                browser: "Firefox",
+                description: "user.js for every Firefox profile",
+                options,
+                contents: firefox::user_js_with(vertical_tabs, search_suggestions)?,
+                destinations: profile_destinations(FIREFOX_DATA_FOLDERS, "user.js")
+            });
+        }
+    }
+
+    // Zen always has vertical tabs
+    for search_suggestions in [false, true] {
+        let options = Map::from_iter([(s!("search_suggestions"), json!(search_suggestions))]);
+        artifacts.push(Artifact {
+            path: format!("zen/{}/user.js", option_set(&options)),
+            browser: "Zen",
+            description: "user.js for every Zen profile",
+            options,
+            contents: zen::user_js_with(search_suggestions)?,
+            destinations: profile_destinations(ZEN_DATA_FOLDERS, "user.js")
+        });
+    }
+
+    artifacts.extend([
+        Artifact {
+            path: s!("firefox/policies.json"),
+            browser: "Firefox",
+            description: "Firefox enterprise policies",
+            options: Map::new(),
+            contents: serde_json::to_string_pretty(&firefox_policies)?,
+            destinations: destinations(|via| {
+                Some(firefox::linux_policies_folder(via).join("policies.json"))
+            })
+        },
+        Artifact {
+            path: s!("brave/custom-policy.json"),
+            browser: "Brave",
+            description: "Brave policies",
+            options: Map::new(),
+            contents: serde_json::to_string(brave::validated_policies()?)?,
+            destinations: destinations(|via| brave::linux_policy_file(via, enforcement))
+        },
+        Artifact {
+            path: s!("brave/initial_preferences"),
+            browser: "Brave",
+            description: "preferences new Brave profiles start with",
+            options: Map::new(),
+            contents: serde_json::to_string_pretty(&initial_preferences)?,
+            // Snap and Flatpak apps are read-only
+            destinations: destinations(|via| {
+                let path = "/opt/brave.com/brave/initial_preferences";
+                (via == InstalledVia::Local).then(|| path.into())
+            })
+        },
+        Artifact {
+            path: s!("brave/ChromeFeatureState"),
+            browser: "Brave",
+            description: "keys to merge into the ChromeFeatureState of every Brave data folder",
+            options: Map::new(),
+            contents: serde_json::to_string(&feature_state)?,
+            destinations: PACKAGINGS
+                .into_iter()
+                .zip([
+                    "~/.config/BraveSoftware/Brave-Browser",
+                    "~/snap/brave/current/.config/BraveSoftware/Brave-Browser",
+                    "~/.var/app/com.brave.Browser/config/BraveSoftware/Brave-Browser"
+                ])
+                .map(|(via, base)| (via, Some(format!("{base}/ChromeFeatureState"))))
+                .collect()
+        }
+    ]);
+
+    // Only clenzy's section, the rest of each file is the user's
+    for (file_name, section) in css::sections()? {
+        let browsers = [("Firefox", FIREFOX_DATA_FOLDERS), ("Zen", ZEN_DATA_FOLDERS)];
+        for (browser, data_folders) in browsers {
+            artifacts.push(Artifact {
+                path: format!("{}/chrome/{file_name}", browser.to_lowercase()),
+                browser,
+                description: "clenzy's section of a stylesheet for every profile",
+                options: Map::new(),
+                contents: section.clone(),
+                destinations: profile_destinations(data_folders, &format!("chrome/{file_name}"))
+            });
+        }
+    }
+
+    Ok(artifacts)
+}
+
+fn destinations(
+    f: impl Fn(InstalledVia) -> Option<std::path::PathBuf>
+) -> Vec<(InstalledVia, Option<String>)> {
+    PACKAGINGS.into_iter().map(|via| (via, f(via).map(|p| p.display().to_string()))).collect()
+}
+
+fn profile_destinations(
+    data_folders: [&str; 3],
+    file: &str
+) -> Vec<(InstalledVia, Option<String>)> {
+    PACKAGINGS
+        .into_iter()
+        .zip(data_folders)
+        .map(|(via, data_folder)| (via, Some(format!("{data_folder}/<profile>/{file}"))))
+        .collect()
+}
+
+// Names the directory for a set of options, like no-search-suggestions/vertical-tabs
+fn option_set(options: &Map<String, Value>) -> String {
+    options
+        .iter()
+        .map(|(name, enabled)| {
+            let name = name.replace('_', "-");
+            if enabled.as_bool() == Some(true) { name } else { format!("no-{name}") }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn manifest(artifacts: &[Artifact]) -> Value {
+    let enforcement = args().enforcement.to_possible_value().map(|v| v.get_name().to_owned());
+    let files = artifacts
+        .iter()
+        .map(|artifact| {
+            let destinations = artifact
+                .destinations
+                .iter()
+                .map(|(via, destination)| (via.to_string().to_lowercase(), json!(destination)))
+                .collect::<Map<_, _>>();
+
+            json!({
+                "path": artifact.path,
+                "browser": artifact.browser,
+                "description": artifact.description,
+                "options": artifact.options,
+                "destinations": destinations
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "clenzy": env!("CARGO_PKG_VERSION"),
+        "platform": "linux",
+        "options": {
+            "enforcement": enforcement
+        },
+        "placeholders": {
+            "~": "the home directory of each user",
+            "<profile>": "each profile directory"
+        },
+        "files": files,
+        "not_exported": NOT_EXPORTED
+    })
+}
@@ -0,0 +1,158 @@
+use crate::{
+    Enforcement, Hardening, s, util::{args, content_settings, get_or_insert_obj, transaction::Transaction}
+};
+use color_eyre::eyre::{bail, WrapErr};
+use serde_json::{Map, Value, json};
+use std::path::Path;
+
+// Firefox's permission for cookies that are never cleared, and Chromium's content setting for it
+const FIREFOX_COOKIE_ALLOW: u32 = 1;
+const CHROMIUM_ALLOW: u32 = 1;
+const CHROMIUM_BLOCK: u32 = 2;
+const CHROMIUM_ASK: u32 = 3;
+// Cookies are kept until the browser closes
+const CHROMIUM_SESSION_ONLY: u32 = 4;
+
+pub fn kept_cookies() -> color_eyre::Result<Vec<String>> {
+    args()
+        .keep_cookies
+        .iter()
+        .map(|origin| {
+            content_settings::parse_origin(origin)
+                .wrap_err_with(|| format!("Invalid site to keep cookies of {origin}"))
+        })
+        .collect()
+}
+
+// Invalid ones already stopped clenzy in main before anything was written
+fn kept() -> Vec<String> {
+    kept_cookies().unwrap_or_default()
+}
+
+// user.js snippets for the level, for Firefox and Zen
+pub fn firefox_snippets() -> Vec<&'static str> {
+    let standard = include_str!("../../snippets/firefox_common/standard_tracking.js");
+    let balanced = include_str!("../../snippets/firefox_common/hardening/balanced.js");
+    let strict = include_str!("../../snippets/firefox_common/hardening/strict.js");
+
+    match args().hardening {
+        Hardening::Off => vec![standard],
+        Hardening::Balanced => vec![balanced],
+        Hardening::Strict => vec![balanced, strict]
+    }
+}
+
+pub fn firefox_policies(locked: bool) -> Map<String, Value> {
+    let level = args().hardening;
+    let mut policies = Map::new();
+    if level >= Hardening::Balanced {
+        let tracking_protection = json!({
+            "Value": true,
+            "Cryptomining": true,
+            "Fingerprinting": true,
+            "EmailTracking": true,
+            "Category": "strict",
+            "Locked": locked
+        });
+        policies.insert(s!("EnableTrackingProtection"), tracking_protection);
+        let https_only = if locked { "force_enabled" } else { "enabled" };
+        policies.insert(s!("HttpsOnlyMode"), json!(https_only));
+    }
+
+    if level >= Hardening::Strict {
+        let sanitize = json!({ "Cache": true, "Cookies": true, "Locked": locked });
+        policies.insert(s!("SanitizeOnShutdown"), sanitize);
+    }
+
+    // Sites allowed to keep cookies aren't cleared on shutdown
+    let kept = kept();
+    if !kept.is_empty() {
+        policies.insert(s!("Cookies"), json!({ "Allow": kept }));
+    }
+
+    policies
+}
+
+// Stages cookie exceptions in permissions.sqlite, Firefox has to be closed
+pub fn firefox_kept_cookies(
+    profile: &Path,
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
+    let permissions = kept()
+        .into_iter()
+        .map(|origin| (origin, "cookie", Some(FIREFOX_COOKIE_ALLOW)))
+        .collect::<Vec<_>>();
+
+    content_settings::set_firefox_permissions(profile, &permissions, transaction)
+}
+
+// Shields and cookie settings in a Brave profile's Preferences
+pub fn apply_chromium_preferences(prefs: &mut Map<String, Value>) -> color_eyre::Result<()> {
+    let level = args().hardening;
+    if level >= Hardening::Balanced {
+        let Some(profile) = get_or_insert_obj(prefs, "profile") else {
+            bail!("Failed to get profile object");
+        };
+        profile.insert(s!("cookie_controls_mode"), json!(1));
+
+        if let Some(values) = get_or_insert_obj(profile, "default_content_setting_values") {
+            // Shields: block trackers and ads aggressively, and always upgrade to HTTPS
+            values.insert(s!("braveAds"), json!(CHROMIUM_BLOCK));
+            values.insert(s!("trackers"), json!(CHROMIUM_BLOCK));
+            values.insert(s!("cosmeticFiltering"), json!(CHROMIUM_BLOCK));
+            values.insert(s!("httpsUpgrades"), json!(CHROMIUM_BLOCK));
+            // Blocking is strict fingerprinting protection, asking is the standard one
+            let fingerprinting =
+                if level >= Hardening::Strict { CHROMIUM_BLOCK } else { CHROMIUM_ASK };
+            values.insert(s!("fingerprintingV2"), json!(fingerprinting));
+            if level >= Hardening::Strict {
+                values.insert(s!("cookies"), json!(CHROMIUM_SESSION_ONLY));
+            }
+        }
+
+        // Aggressive also hides first party ads, standard leaves them
+        if let Some(cosmetic_filtering) = get_or_insert_obj(profile, "content_settings")
+            .and_then(|c| get_or_insert_obj(c, "exceptions"))
+            .and_then(|e| get_or_insert_obj(e, "cosmeticFiltering"))
+        {
+            cosmetic_filtering
+                .insert(s!("*,https://firstparty/*"), json!({ "setting": CHROMIUM_BLOCK }));
+        }
+    }
+
+    if level >= Hardening::Strict
+        && let Some(clear_data) =
+            get_or_insert_obj(prefs, "browser").and_then(|b| get_or_insert_obj(b, "clear_data"))
+    {
+        clear_data.insert(s!("cache_on_exit"), json!(true));
+    }
+
+    for origin in kept() {
+        content_settings::insert_chromium_exception(prefs, "cookies", &origin, CHROMIUM_ALLOW)?;
+    }
+
+    Ok(())
+}
+
+pub fn chromium_policies() -> Map<String, Value> {
+    let level = args().hardening;
+    let mut policies = Map::new();
+    if level >= Hardening::Balanced {
+        policies.insert(s!("BlockThirdPartyCookies"), json!(true));
+        // Chromium can't turn it on without forcing it, so only do that when prefs are pinned
+        if args().enforcement == Enforcement::Locked {
+            policies.insert(s!("HttpsOnlyMode"), json!("force_enabled"));
+        }
+    }
+
+    if level >= Hardening::Strict {
+        policies.insert(s!("DefaultCookiesSetting"), json!(CHROMIUM_SESSION_ONLY));
+    }
+
+    let kept = kept();
+    if !kept.is_empty() {
+        policies.insert(s!("CookiesAllowedForUrls"), json!(kept));
+    }
+
+    policies
+}
@@ -0,0 +1,281 @@
+use crate::util::{UnwrapOrExit, args};
+use std::{
+    fmt::Display, fs, path::{Path, PathBuf}, thread, time::{Duration, Instant}
+};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, Signal, System};
+use tracing::{debug, info, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// What the processes holding each kind of lock are called, lowercased
+const FIREFOX_PROCESSES: [&str; 5] = ["firefox", "zen", "librewolf", "floorp", "waterfox"];
+const CHROMIUM_PROCESSES: [&str; 2] = ["brave", "chrom"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockKind {
+    // Per profile directory
+    Firefox,
+    // Per user data directory, shared by every profile inside it
+    Chromium
+}
+
+#[derive(Clone, Debug)]
+pub struct ProfileLock {
+    pub path: PathBuf,
+    pub host: Option<String>,
+    pub pid: Option<u32>
+}
+
+impl Display for ProfileLock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.host, self.pid) {
+            (Some(host), Some(pid)) => write!(f, "PID {pid} on {host}"),
+            (None, Some(pid)) => write!(f, "PID {pid}"),
+            _ => write!(f, "unknown process")
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum LockAction {
+    Wait,
+    Close,
+    Skip,
+    Ignore
+}
+
+impl Display for LockAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Wait => write!(f, "Wait for the browser to close"),
+            Self::Close => write!(f, "Close the browser"),
+            Self::Skip => write!(f, "Skip"),
+            Self::Ignore => write!(f, "Continue anyway")
+        }
+    }
+}
+
+pub fn find_lock(kind: LockKind, dir: &Path) -> Option<ProfileLock> {
+    let lock = match kind {
+        LockKind::Firefox => firefox_lock(dir),
+        LockKind::Chromium => chromium_lock(dir)
+    };
+
+    if let Some(lock) = &lock {
+        debug!(path = %lock.path.display(), %lock, "Found profile lock");
+    }
+
+    lock
+}
+
+// Firefox holds an fcntl lock on .parentlock (parent.lock on Windows) for as long as it runs,
+// and also leaves a `lock` symlink pointing to `<ip>:+<pid>` on unix.
+fn firefox_lock(profile: &Path) -> Option<ProfileLock> {
+    if cfg!(windows) {
+        return held_lock_file(&profile.join("parent.lock"));
+    }
+
+    let symlink = profile.join("lock");
+    let target = fs::read_link(&symlink).ok();
+    let owner = target.as_ref().and_then(|t| {
+        let (host, pid) = t.to_str()?.rsplit_once(':')?;
+        Some((host.to_owned(), pid.trim_start_matches('+').parse::<u32>().ok()?))
+    });
+
+    let parent_lock = profile.join(".parentlock");
+    if parent_lock.exists() {
+        // The fcntl lock has no host, the symlink next to it says where Firefox runs
+        return fcntl_lock(&parent_lock)
+            .map(|lock| ProfileLock { host: owner.map(|(host, _)| host), ..lock });
+    }
+
+    let (host, pid) = owner?;
+    if is_local_host(&host) && !is_alive(pid) {
+        debug!(path = %symlink.display(), %pid, "Ignoring stale Firefox lock");
+        return None;
+    }
+
+    Some(ProfileLock { path: symlink, host: Some(host), pid: Some(pid) })
+}
+
+// Firefox writes its IP address into the lock, Chromium the host name
+fn is_local_host(host: &str) -> bool {
+    host.starts_with("127.") || Some(host) == System::host_name().as_deref()
+}
+
+// Chromium leaves a `SingletonLock` symlink pointing to `<hostname>-<pid>` on unix,
+// and holds `lockfile` open on Windows.
+fn chromium_lock(data_folder: &Path) -> Option<ProfileLock> {
+    if cfg!(windows) {
+        return held_lock_file(&data_folder.join("lockfile"));
+    }
+
+    let symlink = data_folder.join("SingletonLock");
+    let target = fs::read_link(&symlink).ok()?;
+    let (host, pid) = target.to_str()?.rsplit_once('-')?;
+    let pid = pid.parse::<u32>().ok()?;
+
+    if is_local_host(host) && !is_alive(pid) {
+        debug!(path = %symlink.display(), %pid, "Ignoring stale Chromium lock");
+        return None;
+    }
+
+    Some(ProfileLock { path: symlink, host: Some(host.to_owned()), pid: Some(pid) })
+}
+
+// Windows browsers open their lock file without sharing, so opening it fails while they run
+fn held_lock_file(path: &Path) -> Option<ProfileLock> {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    let err = fs::OpenOptions::new().read(true).write(true).open(path).err()?;
+    (err.raw_os_error() == Some(ERROR_SHARING_VIOLATION))
+        .then(|| ProfileLock { path: path.to_path_buf(), host: None, pid: None })
+}
+
+#[cfg(unix)]
+#[allow(clippy::cast_possible_truncation)]
+fn fcntl_lock(path: &Path) -> Option<ProfileLock> {
+    use std::os::fd::AsRawFd;
+
+    let file = fs::OpenOptions::new().read(true).write(true).open(path).ok()?;
+
+    // SAFETY: flock is a plain C struct, and F_GETLK only writes into it
+    let mut fl: libc::flock = unsafe { std::mem::zeroed() };
+    fl.l_type = libc::F_WRLCK as libc::c_short;
+    fl.l_whence = libc::SEEK_SET as libc::c_short;
+
+    // SAFETY: the fd stays valid for the lifetime of `file`
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &raw mut fl) } == -1 {
+        debug!(path = %path.display(), "F_GETLK failed");
+        return None;
+    }
+
+    (i32::from(fl.l_type) != libc::F_UNLCK).then(|| ProfileLock {
+        path: path.to_path_buf(),
+        host: None,
+        pid: u32::try_from(fl.l_pid).ok().filter(|pid| *pid > 0)
+    })
+}
+
+#[cfg(not(unix))]
+fn fcntl_lock(_path: &Path) -> Option<ProfileLock> {
+    None
+}
+
+fn is_alive(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing()
+    );
+
+    system.process(pid).is_some()
+}
+
+// Returns whether it's safe to modify the directory
+pub fn ensure_unlocked(kind: LockKind, dir: &Path, name: &dyn Display) -> bool {
+    let Some(lock) = find_lock(kind, dir) else {
+        return true;
+    };
+
+    warn!("{name} is in use by a running browser ({lock})");
+
+    let action = if args().close_browsers {
+        LockAction::Close
+    } else if args().wait {
+        LockAction::Wait
+    } else if args().auto_confirm {
+        info!("Pass --wait or --close-browsers to handle running browsers automatically");
+        LockAction::Skip
+    } else {
+        let mut options = vec![LockAction::Wait, LockAction::Skip, LockAction::Ignore];
+        if lock.pid.is_some() {
+            options.insert(1, LockAction::Close);
+        }
+
+        inquire::Select::new(&format!("What should be done with {name}?"), options)
+            .prompt()
+            .unwrap_or_exit()
+    };
+
+    let timeout = Duration::from_secs(args().lock_timeout);
+    let released = match action {
+        LockAction::Wait => {
+            info!("Waiting up to {}s for {name} to be released", timeout.as_secs());
+            wait_for_release(kind, dir, timeout)
+        }
+        LockAction::Close => close_browser(kind, dir, &lock, timeout),
+        LockAction::Skip => false,
+        LockAction::Ignore => return true
+    };
+
+    if !released {
+        warn!("Skipping {name} because it is still in use");
+    }
+
+    released
+}
+
+fn wait_for_release(kind: LockKind, dir: &Path, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if find_lock(kind, dir).is_none() {
+            return true;
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    find_lock(kind, dir).is_none()
+}
+
+// Asks the browser to exit gracefully, then kills it if it hasn't released the lock in time
+fn close_browser(kind: LockKind, dir: &Path, lock: &ProfileLock, timeout: Duration) -> bool {
+    let Some(pid) = lock.pid.map(Pid::from_u32) else {
+        warn!("Can't close a browser without knowing its PID, waiting instead");
+        return wait_for_release(kind, dir, timeout);
+    };
+
+    // The PID of a browser on another machine means nothing here
+    if let Some(host) = lock.host.as_deref().filter(|host| !is_local_host(host)) {
+        warn!("The browser runs on {host}, waiting for it to close instead");
+        return wait_for_release(kind, dir, timeout);
+    }
+
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing()
+    );
+
+    let Some(process) = system.process(pid) else {
+        return wait_for_release(kind, dir, timeout);
+    };
+
+    // A PID from another PID namespace, like a Flatpak's, can belong to anything
+    let name = process.name().to_string_lossy().to_lowercase();
+    let browsers = match kind {
+        LockKind::Firefox => FIREFOX_PROCESSES.as_slice(),
+        LockKind::Chromium => CHROMIUM_PROCESSES.as_slice()
+    };
+    if !browsers.iter().any(|browser| name.contains(browser)) {
+        warn!("PID {pid} is {name}, not a browser, waiting for the browser to close instead");
+        return wait_for_release(kind, dir, timeout);
+    }
+
+    info!("Closing {} ({pid})", process.name().display());
+    if process.kill_with(Signal::Term).is_none() {
+        // Graceful termination isn't supported on this platform
+        process.kill();
+    }
+
+    if wait_for_release(kind, dir, timeout) {
+        return true;
+    }
+
+    warn!("Browser didn't exit after {}s, killing it", timeout.as_secs());
+    process.kill_with(Signal::Kill).unwrap_or_else(|| process.kill());
+    wait_for_release(kind, dir, POLL_INTERVAL * 10)
+}
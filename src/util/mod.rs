@@ -5,13 +5,24 @@ use color_eyre::eyre::Context;
 use inquire::error::InquireResult;
 use serde_json::{Map, Value};
 use std::{
-    collections::HashSet, fmt::Display, fs, io::{Read, stdin}, path::{Path, PathBuf}, process, process::Stdio
+    fmt::Display, fs, path::{Path, PathBuf}, process
 };
-use sysinfo::{ProcessRefreshKind, RefreshKind, System};
 use tracing::{debug, debug_span, info, warn};
 
 pub mod archive;
+pub mod backups;
+pub mod binary;
+pub mod content_settings;
+pub mod elevate;
+pub mod encryption;
+pub mod export;
+pub mod hardening;
+pub mod lock;
 pub mod logging;
+pub mod policy_merge;
+pub mod policy_schema;
+pub mod transaction;
+pub mod users;
 
 pub fn get_or_insert_obj<'a>(
     map: &'a mut Map<String, Value>,
@@ -36,7 +47,7 @@ pub fn roaming_data_base() -> Option<PathBuf> {
     if cfg!(any(target_os = "macos", target_os = "windows")) {
         dirs::data_dir()
     } else {
-        dirs::home_dir()
+        users::home_dir()
     }
 }
 
@@ -44,7 +55,7 @@ pub fn local_data_base() -> Option<PathBuf> {
     if cfg!(any(target_os = "macos", target_os = "windows")) {
         dirs::data_local_dir()
     } else {
-        dirs::config_local_dir()
+        users::config_dir()
     }
 }
 
@@ -66,7 +77,7 @@ pub fn local_app_bases() -> impl Iterator<Item = PathBuf> {
 #[rustfmt::skip]
 pub fn local_snap_base() -> Option<PathBuf> {
     if cfg!(target_os = "linux") {
-        dirs::home_dir().map(|p| p.join("snap"))
+        users::home_dir().map(|p| p.join("snap"))
     } else {
         None
     }
@@ -75,15 +86,15 @@ pub fn local_snap_base() -> Option<PathBuf> {
 #[rustfmt::skip]
 pub fn flatpak_base() -> Option<PathBuf> {
     if cfg!(target_os = "linux") {
-        dirs::home_dir().map(|p| p.join(".var/app"))
+        users::home_dir().map(|p| p.join(".var/app"))
     } else {
         None
     }
 }
 
-// 202501192003
+// 20250119200345
 pub fn timestamp() -> String {
-    chrono::Local::now().format("%Y%m%d%H%M").to_string()
+    chrono::Local::now().format("%Y%m%d%H%M%S").to_string()
 }
 
 pub fn fetch_text(name: &str, url: &str) -> color_eyre::Result<String> {
@@ -112,7 +123,7 @@ pub fn validate_profile_dir(profile: &Path) -> bool {
     let children = children
         .into_iter()
         .filter_map(Result::ok)
-        .filter(|c| c.file_type().map(|f| f.is_file() || f.is_dir()).unwrap_or(false))
+        .filter(|c| c.file_type().is_ok_and(|f| f.is_file() || f.is_dir()))
         .count();
 
     // If no files or only times.json (on Firefox)
@@ -142,54 +153,6 @@ pub fn select_profiles<P: Display, B: Browser>(mut profiles: Vec<P>, selected: &
     }
 }
 
-fn get_matching_running_processes(system: &mut System, name: &str) -> String {
-    let lower_name = name.to_lowercase();
-    system.refresh_specifics(RefreshKind::nothing().with_processes(ProcessRefreshKind::default()));
-    let processes = system.processes();
-
-    debug!("Found {} processes total", processes.len());
-
-    let running_instances = processes
-        .values()
-        .filter_map(|p| {
-            let name = p.name().to_str()?;
-            name.to_lowercase().contains(&lower_name).then_some(name)
-        })
-        .collect::<HashSet<_>>();
-
-    running_instances.into_iter().collect::<Vec<_>>().join(", ")
-}
-
-pub fn check_if_running(system: &mut System, browser_name: &str) {
-    if args().auto_confirm {
-        return;
-    }
-
-    let processes = get_matching_running_processes(system, browser_name);
-    if processes.is_empty() {
-        return;
-    }
-
-    warn!("Please close all instances before debloating ({processes})");
-    info!("Press enter to continue");
-    if let Err(why) = stdin().read_exact(&mut [0_u8]) {
-        warn!(err = ?why, "Error reading stdin, exiting");
-        process::exit(1);
-    }
-
-    let processes = get_matching_running_processes(system, browser_name);
-    if processes.is_empty() {
-        return;
-    }
-
-    warn!("Some processes are still running ({processes})");
-
-    let should_continue = inquire::prompt_confirmation("Continue anyway? (y/n)").unwrap_or_exit();
-    if !should_continue {
-        process::exit(1);
-    }
-}
-
 pub fn start_fetch_resource<F, O>(f: F, browser_name: &'static str)
 where
     F: Fn() -> color_eyre::Result<O> + Send + 'static
@@ -267,33 +230,9 @@ pub fn elevate_and_run(flag: &str) -> color_eyre::Result<()> {
 
     let status = std::process::Command::new("powershell")
         .args(&["-Command", &ps_script])
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .stdin(Stdio::null())
-        .status()
-        .wrap_err("Failed to run powershell")?;
-
-    if !status.success() {
-        color_eyre::eyre::bail!("got non-zero exit code: {status}");
-    }
-
-    Ok(())
-}
-
-#[cfg(target_os = "linux")]
-pub fn elevate_and_run(flag: &str) -> color_eyre::Result<()> {
-    let mut args: Vec<_> = std::env::args().collect();
-    if let Some(absolute_path) = std::env::current_exe().ok().as_deref().and_then(Path::to_str) {
-        args[0] = absolute_path.to_string();
-    }
-
-    args.push(flag.to_string());
-
-    let status = process::Command::new("sudo")
-        .args(args)
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .stdin(Stdio::null())
+        .stdout(process::Stdio::inherit())
+        .stderr(process::Stdio::inherit())
+        .stdin(process::Stdio::null())
         .status()
         .wrap_err("Failed to run powershell")?;
 
@@ -306,7 +245,7 @@ pub fn elevate_and_run(flag: &str) -> color_eyre::Result<()> {
 
 // TODO this is a mess, clean up
 pub fn process_single_policies(args: &Args, installations: &[&Installation], post: bool) {
-    if !args.policies && !args.windows_brave_policies && !args.linux_brave_policies && !args.linux_firefox_policies {
+    if !args.policies && !args.windows_brave_policies {
         return;
     }
 
@@ -327,30 +266,92 @@ pub fn process_single_policies(args: &Args, installations: &[&Installation], pos
     }
 
     #[cfg(target_os = "linux")]
-    if post || args.linux_brave_policies {
-        let explicit = args.linux_brave_policies;
+    if post {
         if installations.iter().any(|i| i.browser_name == Brave::name()) {
-            try_policies_or_fail(
-                brave::create_policies_linux(args.backup, explicit),
-                explicit
-            );
-        } else if explicit {
-            warn!("no brave installation found");
+            try_policies_or_fail(brave::create_policies_linux(installations, args.backup), false);
         }
-    }
 
-    #[cfg(target_os = "linux")]
-    if post || args.linux_firefox_policies {
-        let explicit = args.linux_firefox_policies;
         if installations.iter().any(|i| i.browser_name == "Firefox") {
             try_policies_or_fail(
-                crate::firefox::create_linux_policies_file(args.backup, explicit),
-                explicit
+                crate::firefox::create_linux_policies_file(installations, args.backup),
+                false
             );
-        } else if explicit {
-            warn!("no firefox installation found");
         }
+
+        verify_linux_policies(installations);
+    }
+}
+
+// Reports whether each installation will actually read the policies we wrote for it
+#[cfg(target_os = "linux")]
+fn verify_linux_policies(installations: &[&Installation]) {
+    for installation in installations {
+        let result = match installation.browser_name {
+            name if name == Brave::name() => brave::verify_linux_policies(installation),
+            "Firefox" => crate::firefox::verify_linux_policies(installation),
+            _ => continue
+        };
+
+        match result {
+            Ok(path) => logging::success(&format!(
+                "{installation} will read clenzy's policies from {}",
+                path.display()
+            )),
+            Err(why) => warn!("{installation} won't read clenzy's policies: {why}")
+        }
+    }
+}
+
+// Validates every policy file already on this system, returns whether all of them are valid
+pub fn check_policies(installations: &[&Installation]) -> bool {
+    let mut all_valid = true;
+    let mut found = false;
+
+    for path in crate::firefox::installed_policy_files(installations) {
+        found = true;
+        let name = path.display().to_string();
+        match crate::firefox::read_policies(&path) {
+            Ok(policies) => {
+                all_valid &= report_violations(&name, &policy_schema::validate_firefox(&policies));
+            }
+            Err(why) => {
+                all_valid = false;
+                warn!(err = ?why, "Failed to read {name}");
+            }
+        }
+    }
+
+    for (name, policies) in brave::installed_policies() {
+        found = true;
+        match policies {
+            Ok(policies) => {
+                all_valid &= report_violations(&name, &policy_schema::validate_chromium(&policies));
+            }
+            Err(why) => {
+                all_valid = false;
+                warn!(err = ?why, "Failed to read {name}");
+            }
+        }
+    }
+
+    if !found {
+        info!("No policy files found");
+    }
+
+    all_valid
+}
+
+fn report_violations(name: &str, violations: &[policy_schema::Violation]) -> bool {
+    if violations.is_empty() {
+        logging::success(&format!("{name} is valid"));
+        return true;
     }
+
+    for violation in violations {
+        warn!("{name}: {violation}");
+    }
+
+    false
 }
 
 fn try_policies_or_fail(r: color_eyre::Result<()>, explicit: bool) {
@@ -364,11 +365,18 @@ fn try_policies_or_fail(r: color_eyre::Result<()>, explicit: bool) {
     }
 }
 
-pub fn should_elevate() -> bool {
+// Whether a write failed only because we need elevated permissions
+pub fn is_permission_denied(err: &color_eyre::Report) -> bool {
+    err.chain()
+        .filter_map(|e| e.downcast_ref::<std::io::Error>())
+        .any(|e| e.kind() == std::io::ErrorKind::PermissionDenied)
+}
+
+pub fn should_elevate(action: &str) -> bool {
     if args().auto_confirm {
         return true;
-    };
+    }
 
-    info!("Policy creation requires elevated permissions.");
+    info!("{action} requires elevated permissions.");
     inquire::prompt_confirmation("Request elevated permissions? (y/n)").unwrap_or_exit()
 }
@@ -0,0 +1,198 @@
+use color_eyre::eyre::{WrapErr, bail};
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeSet, fmt::Display, fs, path::Path
+};
+use tracing::{debug, warn};
+
+// A path to a single value inside a policies object, like `FirefoxHome` -> `TopSites`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KeyPath(Vec<String>);
+
+impl Display for KeyPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join("."))
+    }
+}
+
+#[derive(Debug)]
+pub struct Conflict {
+    pub key: KeyPath,
+    pub existing: Value,
+    pub ours: Value
+}
+
+#[derive(Debug)]
+pub struct Merged {
+    pub policies: Map<String, Value>,
+    // Every key we set, so the next run knows what it may update or remove
+    pub owned: BTreeSet<KeyPath>,
+    pub conflicts: Vec<Conflict>,
+    pub removed: Vec<KeyPath>
+}
+
+impl Merged {
+    pub fn report(&self, name: &str) {
+        for conflict in &self.conflicts {
+            warn!(
+                "{name}: keeping existing {} = {}, clenzy wanted {}",
+                conflict.key, conflict.existing, conflict.ours
+            );
+        }
+
+        for key in &self.removed {
+            debug!("{name}: removed {key} that clenzy no longer sets");
+        }
+    }
+}
+
+// Merges our policies into someone else's. Foreign keys are left alone, objects are merged key by
+// key, and a value that differs from ours is only replaced if an earlier run of ours set it.
+pub fn merge(
+    existing: &Map<String, Value>,
+    ours: &Map<String, Value>,
+    previously_owned: &BTreeSet<KeyPath>
+) -> Merged {
+    let mut merged = Merged {
+        policies: existing.clone(),
+        owned: BTreeSet::new(),
+        conflicts: Vec::new(),
+        removed: Vec::new()
+    };
+
+    let mut leaves = Vec::new();
+    collect_leaves(ours, &mut Vec::new(), &mut leaves);
+
+    for (key, value) in leaves {
+        match get(&merged.policies, &key) {
+            Lookup::Missing => {
+                set(&mut merged.policies, &key, value.clone());
+                merged.owned.insert(key);
+            }
+            Lookup::Found(existing) if existing == value => {
+                // Someone else may have set the same value, which doesn't make it ours to remove
+                if previously_owned.contains(&key) {
+                    merged.owned.insert(key);
+                }
+            }
+            Lookup::Found(_) if previously_owned.contains(&key) => {
+                set(&mut merged.policies, &key, value.clone());
+                merged.owned.insert(key);
+            }
+            Lookup::Found(existing) | Lookup::Blocked(existing) => {
+                let conflict = Conflict { key, existing: existing.clone(), ours: value.clone() };
+                merged.conflicts.push(conflict);
+            }
+        }
+    }
+
+    for key in previously_owned.difference(&merged.owned) {
+        let still_ours = merged.conflicts.iter().all(|c| &c.key != key);
+        if still_ours && remove(&mut merged.policies, &key.0) {
+            merged.removed.push(key.clone());
+        }
+    }
+
+    merged
+}
+
+fn collect_leaves<'a>(
+    map: &'a Map<String, Value>,
+    prefix: &mut Vec<String>,
+    leaves: &mut Vec<(KeyPath, &'a Value)>
+) {
+    for (key, value) in map {
+        prefix.push(key.clone());
+        match value {
+            Value::Object(inner) if !inner.is_empty() => collect_leaves(inner, prefix, leaves),
+            value => leaves.push((KeyPath(prefix.clone()), value))
+        }
+        prefix.pop();
+    }
+}
+
+enum Lookup<'a> {
+    Missing,
+    Found(&'a Value),
+    // A parent of the key is already set to something other than an object
+    Blocked(&'a Value)
+}
+
+fn get<'a>(map: &'a Map<String, Value>, key: &KeyPath) -> Lookup<'a> {
+    let Some((last, parents)) = key.0.split_last() else {
+        return Lookup::Missing;
+    };
+
+    let mut current = map;
+    for part in parents {
+        match current.get(part) {
+            None => return Lookup::Missing,
+            Some(Value::Object(inner)) => current = inner,
+            Some(other) => return Lookup::Blocked(other)
+        }
+    }
+
+    current.get(last).map_or(Lookup::Missing, Lookup::Found)
+}
+
+fn set(map: &mut Map<String, Value>, key: &KeyPath, value: Value) {
+    let Some((last, parents)) = key.0.split_last() else {
+        return;
+    };
+
+    let mut current = map;
+    for part in parents {
+        let Some(inner) = super::get_or_insert_obj(current, part) else {
+            return;
+        };
+        current = inner;
+    }
+
+    current.insert(last.clone(), value);
+}
+
+// Removes the key and any objects it leaves empty, returns whether it existed
+fn remove(map: &mut Map<String, Value>, key: &[String]) -> bool {
+    match key {
+        [] => false,
+        [last] => map.remove(last).is_some(),
+        [first, rest @ ..] => {
+            let Some(Value::Object(inner)) = map.get_mut(first) else {
+                return false;
+            };
+
+            let removed = remove(inner, rest);
+            if removed && inner.is_empty() {
+                map.remove(first);
+            }
+
+            removed
+        }
+    }
+}
+
+// The keys clenzy owns are kept next to the policies file, where browsers won't read them
+pub fn read_owned(path: &Path) -> color_eyre::Result<BTreeSet<KeyPath>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeSet::new()),
+        Err(why) => return Err(why).wrap_err_with(|| format!("Failed to read {}", path.display()))
+    };
+
+    let Value::Array(keys) = serde_json::from_str::<Value>(&contents)
+        .wrap_err_with(|| format!("Failed to parse {}", path.display()))?
+    else {
+        bail!("{} isn't a list of keys", path.display());
+    };
+
+    Ok(keys
+        .iter()
+        .filter_map(Value::as_array)
+        .map(|parts| KeyPath(parts.iter().filter_map(Value::as_str).map(String::from).collect()))
+        .collect())
+}
+
+pub fn serialize_owned(owned: &BTreeSet<KeyPath>) -> serde_json::Result<String> {
+    let keys = owned.iter().map(|k| Value::from(k.0.clone())).collect::<Vec<_>>();
+    serde_json::to_string_pretty(&keys)
+}
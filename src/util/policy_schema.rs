@@ -0,0 +1,180 @@
+use color_eyre::eyre::bail;
+use serde_json::{Map, Value};
+use std::{fmt::Display, sync::LazyLock};
+use tracing::warn;
+
+// Trimmed from Firefox's policies-schema.json, in the same format
+static FIREFOX_SCHEMA: LazyLock<Value> = LazyLock::new(|| {
+    serde_json::from_str(include_str!("../../snippets/firefox/policies-schema.json"))
+        .expect("to parse firefox policies schema")
+});
+
+// Trimmed from Chromium's policy_templates.json plus Brave's own policies
+static CHROMIUM_TEMPLATES: LazyLock<Map<String, Value>> = LazyLock::new(|| {
+    let templates: Value =
+        serde_json::from_str(include_str!("../../snippets/brave/policy_templates.json"))
+            .expect("to parse brave policy templates");
+
+    templates
+        .get("policy_definitions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|p| Some((p.get("name")?.as_str()?.to_owned(), p.clone())))
+        .collect()
+});
+
+#[derive(Debug)]
+pub struct Violation {
+    pub key: String,
+    pub message: String
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+// Browsers silently ignore policies they don't understand, so refuse to write any
+pub fn ensure_valid(name: &str, violations: &[Violation]) -> color_eyre::Result<()> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    for violation in violations {
+        warn!("{name}: {violation}");
+    }
+
+    bail!("{name} failed schema validation with {} problems", violations.len());
+}
+
+fn violation(key: &str, message: impl Into<String>) -> Violation {
+    Violation { key: key.to_owned(), message: message.into() }
+}
+
+// Validates the contents of the `policies` key of a policies.json
+pub fn validate_firefox(policies: &Map<String, Value>) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    validate_object(&FIREFOX_SCHEMA, policies, "", &mut violations);
+    violations
+}
+
+fn validate_object(
+    schema: &Value,
+    object: &Map<String, Value>,
+    path: &str,
+    violations: &mut Vec<Violation>
+) {
+    // Objects without listed properties take anything, like `Preferences` or `ExtensionSettings`
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return;
+    };
+
+    for (key, value) in object {
+        let key_path = if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+        match properties.get(key) {
+            Some(schema) => validate_value(schema, value, &key_path, violations),
+            None if path.is_empty() => violations.push(violation(&key_path, "unknown policy")),
+            None => violations.push(violation(&key_path, "unknown key"))
+        }
+    }
+}
+
+fn validate_value(schema: &Value, value: &Value, path: &str, violations: &mut Vec<Violation>) {
+    let types = match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => return
+    };
+
+    let Some(matched) = types.iter().copied().find(|t| matches_type(t, value)) else {
+        violations.push(violation(path, format!("expected {}, got {value}", types.join(" or "))));
+        return;
+    };
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        let allowed = Value::from(allowed.clone());
+        violations.push(violation(path, format!("{value} isn't one of {allowed}")));
+    }
+
+    match (matched, value) {
+        ("object", Value::Object(object)) => validate_object(schema, object, path, violations),
+        ("array", Value::Array(items)) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_value(item_schema, item, &format!("{path}[{i}]"), violations);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn matches_type(t: &str, value: &Value) -> bool {
+    match t {
+        "boolean" => value.is_boolean(),
+        "string" | "URL" | "URLorEmpty" | "origin" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        // Either already parsed, or a string that will be
+        "JSON" => value.is_object() || value.is_array() || value.is_string(),
+        _ => true
+    }
+}
+
+// Validates a Chromium policy map, like Brave's managed JSON on Linux
+pub fn validate_chromium(policies: &Map<String, Value>) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for (key, value) in policies {
+        let Some(template) = CHROMIUM_TEMPLATES.get(key) else {
+            violations.push(violation(key, "unknown policy"));
+            continue;
+        };
+
+        let t = template.get("type").and_then(Value::as_str).unwrap_or_default();
+        let valid = match t {
+            "main" => value.is_boolean(),
+            "int" => value.is_i64(),
+            "string" => value.is_string(),
+            "list" => value.as_array().is_some_and(|l| l.iter().all(Value::is_string)),
+            "dict" => value.is_object() || value.is_string(),
+            "int-enum" | "string-enum" => {
+                let items = template.get("items").and_then(Value::as_array);
+                if !items.is_some_and(|items| items.contains(value)) {
+                    let items = Value::from(items.cloned().unwrap_or_default());
+                    violations.push(violation(key, format!("{value} isn't one of {items}")));
+                    continue;
+                }
+
+                true
+            }
+            _ => true
+        };
+
+        if !valid {
+            let expected = if t == "main" { "boolean" } else { t };
+            violations.push(violation(key, format!("expected {expected}, got {value}")));
+        }
+    }
+
+    violations
+}
+
+// Windows registry values and our snippet store booleans as 0 and 1, JSON policies need real ones
+pub fn coerce_chromium(policies: &mut Map<String, Value>) {
+    for (key, value) in policies.iter_mut() {
+        let is_main = CHROMIUM_TEMPLATES
+            .get(key)
+            .and_then(|t| t.get("type"))
+            .is_some_and(|t| t == "main");
+
+        if is_main && let Some(n) = value.as_u64().filter(|n| *n <= 1) {
+            *value = Value::Bool(n == 1);
+        }
+    }
+}
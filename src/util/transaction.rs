@@ -0,0 +1,210 @@
+use color_eyre::eyre::{ContextCompat, WrapErr};
+use std::{
+    fs, fs::File, io::Write, mem, path::{Path, PathBuf}, sync::atomic::{AtomicUsize, Ordering}
+};
+use tracing::{debug, warn};
+use crate::util::users;
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+struct Staged {
+    target: PathBuf,
+    // None removes the target
+    temp: Option<PathBuf>,
+    // None if the target didn't exist before
+    original: Option<Vec<u8>>
+}
+
+// Collects every write for one installation so they land together or not at all.
+// Content is staged into a temp file next to the target and only renamed over it on commit.
+#[derive(Default)]
+pub struct Transaction {
+    staged: Vec<Staged>
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, path: &Path, contents: impl AsRef<[u8]>) -> color_eyre::Result<()> {
+        let temp = stage(path, contents.as_ref())?;
+        debug!(path = %path.display(), temp = %temp.display(), "Staged write");
+        self.push(path, Some(temp))
+    }
+
+    // Removes `path` on commit, and puts it back if the transaction rolls back
+    pub fn remove(&mut self, path: &Path) -> color_eyre::Result<()> {
+        debug!(path = %path.display(), "Staged removal");
+        self.push(path, None)
+    }
+
+    // The temp file holding what will be written to `path`, if a write to it is staged
+    pub fn staged(&self, path: &Path) -> Option<&Path> {
+        self.staged.iter().find(|s| s.target == path).and_then(|s| s.temp.as_deref())
+    }
+
+    fn push(&mut self, path: &Path, temp: Option<PathBuf>) -> color_eyre::Result<()> {
+        // A later change to the same file replaces the earlier one, but keeps the real original
+        if let Some(position) = self.staged.iter().position(|s| s.target == path) {
+            let previous = self.staged.remove(position);
+            if let Some(previous_temp) = &previous.temp {
+                remove_temp(previous_temp);
+            }
+            self.staged.push(Staged { target: previous.target, temp, original: previous.original });
+            return Ok(());
+        }
+
+        let original = match fs::read(path) {
+            Ok(original) => Some(original),
+            Err(why) if why.kind() == std::io::ErrorKind::NotFound => None,
+            Err(why) => {
+                if let Some(temp) = &temp {
+                    remove_temp(temp);
+                }
+                return Err(why)
+                    .wrap_err_with(|| format!("Failed to read original {}", path.display()));
+            }
+        };
+
+        self.staged.push(Staged { target: path.to_path_buf(), temp, original });
+        Ok(())
+    }
+
+    pub fn commit(mut self) -> color_eyre::Result<()> {
+        let staged = mem::take(&mut self.staged);
+        let mut committed = Vec::with_capacity(staged.len());
+
+        let mut staged = staged.into_iter();
+        while let Some(s) = staged.next() {
+            let result = s.temp.as_ref().map_or_else(
+                || remove_target(&s.target),
+                |temp| fs::rename(temp, &s.target)
+            );
+
+            if let Err(why) = result {
+                let err = Err(why).wrap_err_with(|| {
+                    format!("Failed to move {} into place", s.target.display())
+                });
+
+                staged.chain([s]).filter_map(|s| s.temp).for_each(|temp| remove_temp(&temp));
+                rollback(&committed);
+                return err;
+            }
+
+            sync_parent(&s.target);
+            committed.push(s);
+        }
+
+        debug!(len = %committed.len(), "Committed transaction");
+        Ok(())
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        // Never committed, so nothing was touched except the temp files
+        for temp in self.staged.iter().filter_map(|s| s.temp.as_ref()) {
+            remove_temp(temp);
+        }
+    }
+}
+
+// Atomically replaces a single file
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> color_eyre::Result<()> {
+    let mut transaction = Transaction::new();
+    transaction.write(path, contents)?;
+    transaction.commit()
+}
+
+fn stage(target: &Path, contents: &[u8]) -> color_eyre::Result<PathBuf> {
+    let parent = target.parent().wrap_err("Target has no parent directory")?;
+    let name = target.file_name().wrap_err("Target has no file name")?.to_string_lossy();
+    let id = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let temp = parent.join(format!(".{name}.clenzy-{}-{id}.tmp", std::process::id()));
+
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        if let Ok(metadata) = fs::metadata(target) {
+            fs::set_permissions(&temp, metadata.permissions())?;
+            copy_ownership(&metadata, &temp);
+        } else {
+            users::apply_owner(&temp);
+        }
+
+        Ok::<_, std::io::Error>(())
+    })();
+
+    if let Err(why) = result {
+        remove_temp(&temp);
+        return Err(why).wrap_err_with(|| format!("Failed to stage {}", target.display()));
+    }
+
+    Ok(temp)
+}
+
+// Puts back the original bytes of everything that was already renamed
+fn rollback(committed: &[Staged]) {
+    for s in committed.iter().rev() {
+        let result = s.original.as_ref().map_or_else(
+            || fs::remove_file(&s.target).map_err(Into::into),
+            |original| {
+                stage(&s.target, original)
+                    .and_then(|temp| fs::rename(&temp, &s.target).map_err(Into::into))
+            }
+        );
+
+        match result {
+            Ok(()) => debug!(path = %s.target.display(), "Rolled back"),
+            Err(why) => warn!(err = ?why, "Failed to roll back {}", s.target.display())
+        }
+    }
+}
+
+fn remove_target(target: &Path) -> std::io::Result<()> {
+    match fs::remove_file(target) {
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result
+    }
+}
+
+fn remove_temp(temp: &Path) {
+    if let Err(why) = fs::remove_file(temp)
+        && why.kind() != std::io::ErrorKind::NotFound
+    {
+        debug!(err = ?why, path = %temp.display(), "Failed to remove temp file");
+    }
+}
+
+#[cfg(unix)]
+fn copy_ownership(metadata: &fs::Metadata, temp: &Path) {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(temp_metadata) = fs::metadata(temp) else {
+        return;
+    };
+
+    if temp_metadata.uid() == metadata.uid() && temp_metadata.gid() == metadata.gid() {
+        return;
+    }
+
+    if let Err(why) = std::os::unix::fs::chown(temp, Some(metadata.uid()), Some(metadata.gid())) {
+        warn!(err = ?why, path = %temp.display(), "Failed to preserve file ownership");
+    }
+}
+
+#[cfg(not(unix))]
+const fn copy_ownership(_metadata: &fs::Metadata, _temp: &Path) {}
+
+// Makes the rename itself durable
+fn sync_parent(path: &Path) {
+    if cfg!(unix)
+        && let Some(parent) = path.parent()
+        && let Ok(dir) = File::open(parent)
+    {
+        let _ = dir.sync_all();
+    }
+}
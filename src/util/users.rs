@@ -0,0 +1,184 @@
+use crate::Args;
+use color_eyre::eyre::{WrapErr, bail};
+use std::{
+    collections::HashSet, fmt::Display, fs, path::{Path, PathBuf}, sync::Mutex
+};
+use tracing::{debug, warn};
+
+// Defaults from shadow-utils, used when /etc/login.defs doesn't say otherwise
+const DEFAULT_UID_MIN: u32 = 1000;
+const DEFAULT_UID_MAX: u32 = 60000;
+
+// The user whose home is being debloated in system-wide mode
+static CURRENT_USER: Mutex<Option<SystemUser>> = Mutex::new(None);
+
+#[derive(Clone, Debug)]
+pub struct SystemUser {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+    shell: String
+}
+
+impl Display for SystemUser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.uid)
+    }
+}
+
+fn parse_passwd(passwd: &str) -> Vec<SystemUser> {
+    passwd
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .filter_map(|l| {
+            // name:password:uid:gid:gecos:home:shell
+            let mut fields = l.split(':');
+            let name = fields.next()?.to_owned();
+            let uid = fields.nth(1)?.parse().ok()?;
+            let gid = fields.next()?.parse().ok()?;
+            let home = PathBuf::from(fields.nth(1)?);
+            let shell = fields.next().unwrap_or_default().to_owned();
+
+            Some(SystemUser { name, uid, gid, home, shell })
+        })
+        .collect()
+}
+
+fn login_defs_value(key: &str) -> Option<u32> {
+    fs::read_to_string("/etc/login.defs")
+        .ok()?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.starts_with('#'))
+        .find_map(|l| {
+            let mut parts = l.split_whitespace();
+            (parts.next()? == key).then(|| parts.next()?.parse().ok()).flatten()
+        })
+}
+
+fn is_login_shell(shell: &str, valid_shells: Option<&HashSet<String>>) -> bool {
+    if shell.ends_with("nologin") || shell.ends_with("false") {
+        return false;
+    }
+
+    valid_shells.is_none_or(|shells| shells.contains(shell))
+}
+
+// Human accounts from /etc/passwd, or exactly the ones named with --user
+pub fn system_users(args: &Args) -> color_eyre::Result<Vec<SystemUser>> {
+    let passwd = fs::read_to_string("/etc/passwd").wrap_err("Failed to read /etc/passwd")?;
+    let users = parse_passwd(&passwd);
+
+    if !args.users.is_empty() {
+        let mut selected = Vec::with_capacity(args.users.len());
+        for name in &args.users {
+            match users.iter().find(|u| &u.name == name) {
+                Some(user) => selected.push(user.clone()),
+                None => bail!("User {name} not found in /etc/passwd")
+            }
+        }
+
+        return Ok(selected);
+    }
+
+    let min_uid = args.min_uid.or_else(|| login_defs_value("UID_MIN")).unwrap_or(DEFAULT_UID_MIN);
+    let max_uid = args.max_uid.or_else(|| login_defs_value("UID_MAX")).unwrap_or(DEFAULT_UID_MAX);
+    let valid_shells = fs::read_to_string("/etc/shells").ok().map(|shells| {
+        shells
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(String::from)
+            .collect::<HashSet<_>>()
+    });
+
+    debug!(%min_uid, %max_uid, "Filtering users");
+
+    let users = users
+        .into_iter()
+        .filter(|u| (min_uid..=max_uid).contains(&u.uid))
+        .filter(|u| is_login_shell(&u.shell, valid_shells.as_ref()))
+        .filter(|u| {
+            let exists = u.home.is_dir();
+            if !exists {
+                debug!(user = %u, home = %u.home.display(), "Skipping user without a home");
+            }
+            exists
+        })
+        .collect();
+
+    Ok(users)
+}
+
+// Runs `f` with discovery pointed at the user's home, and new files owned by them
+pub fn with_user<T>(user: &SystemUser, f: impl FnOnce() -> T) -> T {
+    set_current(Some(user.clone()));
+    let ret = f();
+    set_current(None);
+    ret
+}
+
+fn set_current(user: Option<SystemUser>) {
+    match CURRENT_USER.lock() {
+        Ok(mut current) => *current = user,
+        Err(why) => warn!(err = ?why, "Current user lock was poisoned")
+    }
+}
+
+fn current() -> Option<SystemUser> {
+    CURRENT_USER.lock().ok()?.clone()
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    current().map_or_else(dirs::home_dir, |u| Some(u.home))
+}
+
+// We can't know another user's XDG_CONFIG_HOME, so assume the default
+pub fn config_dir() -> Option<PathBuf> {
+    current().map_or_else(dirs::config_local_dir, |u| Some(u.home.join(".config")))
+}
+
+// Same for XDG_STATE_HOME, platforms without one keep state with the local data
+pub fn state_dir() -> Option<PathBuf> {
+    current().map_or_else(
+        || dirs::state_dir().or_else(dirs::data_local_dir),
+        |u| Some(u.home.join(".local/state"))
+    )
+}
+
+// The user being debloated in system-wide mode
+pub fn current_name() -> Option<String> {
+    current().map(|u| u.name)
+}
+
+// Hands a file we created in someone else's home over to them
+#[cfg(unix)]
+pub fn apply_owner(path: &Path) {
+    let Some(user) = current() else {
+        return;
+    };
+
+    // Anything outside their home, like an app folder, stays owned by root
+    if !path.starts_with(&user.home) {
+        return;
+    }
+
+    if let Err(why) = std::os::unix::fs::chown(path, Some(user.uid), Some(user.gid)) {
+        warn!(err = ?why, path = %path.display(), "Failed to give {user} ownership");
+    }
+}
+
+#[cfg(not(unix))]
+pub const fn apply_owner(_path: &Path) {}
+
+#[cfg(unix)]
+pub fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and can't fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+pub const fn is_root() -> bool {
+    false
+}
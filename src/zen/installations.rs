@@ -1,5 +1,5 @@
 use crate::{
-    util::{flatpak_base, local_app_bases, local_snap_base, roaming_data_base}, zen::Zen
+    util::{flatpak_base, local_app_bases, local_snap_base, roaming_data_base, users}, zen::Zen
 };
 use std::path::PathBuf;
 use crate::browser::installation::{Installation, InstalledVia};
@@ -32,9 +32,14 @@ fn local_apps() -> Vec<PathBuf> {
 
     let bases = local_app_bases();
     if cfg!(target_os = "macos") {
-        bases.map(|f| f.join("Zen Browser.app/Contents")).collect()
+        bases
+            .flat_map(|f| ["Zen Browser.app", "Zen.app"].map(|app| f.join(app).join("Contents")))
+            .collect()
     } else {
-        Vec::new()
+        bases
+            .chain(users::home_dir())
+            .flat_map(|f| ["zen", "zen-browser"].map(|dir| f.join(dir)))
+            .collect()
     }
 }
 
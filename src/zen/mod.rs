@@ -6,6 +6,10 @@ use installations::installations;
 use tracing::{debug, warn};
 use crate::browser::installation::Installation;
 use crate::util::args;
+use crate::util::content_settings;
+use crate::util::hardening;
+use crate::util::transaction::Transaction;
+use color_eyre::eyre::WrapErr;
 
 pub struct Zen;
 
@@ -22,38 +26,67 @@ impl Browser for Zen {
         Some(resource::get_better_zen_user_js)
     }
 
-    fn debloat(installation: &Installation) -> color_eyre::Result<()> {
-        debloat(installation)
+    fn debloat(
+        installation: &Installation,
+        transaction: &mut Transaction
+    ) -> color_eyre::Result<()> {
+        debloat(installation, transaction)
     }
 }
 
-pub fn debloat(installation: &Installation) -> color_eyre::Result<()> {
+// Better Zen with our overrides, as it gets written to every profile
+pub fn configured_user_js() -> color_eyre::Result<String> {
+    user_js_with(args().search_suggestions)
+}
+
+// The user.js for one set of options, export writes one for each
+pub fn user_js_with(search_suggestions: bool) -> color_eyre::Result<String> {
     // Not all of these will be used but some are
-    let mut custom_overrides = vec![include_str!("../../snippets/firefox_common/betterfox_extra.js")];
-    if args().search_suggestions {
+    let permissions = content_settings::firefox_user_js();
+    let mut custom_overrides = vec![
+        include_str!("../../snippets/firefox_common/betterfox_extra.js"),
+        permissions.as_str()
+    ];
+    custom_overrides.extend(hardening::firefox_snippets());
+    if firefox::common::css::is_enabled() {
+        custom_overrides.push(include_str!("../../snippets/firefox_common/stylesheets.js"));
+    }
+
+    if search_suggestions {
         custom_overrides.push(include_str!("../../snippets/firefox_common/search_suggestions.js"));
     }
 
+    firefox::common::configure_user_js(
+        resource::get_better_zen_user_js()?,
+        &custom_overrides.join("\n")
+    )
+}
+
+pub fn debloat(
+    installation: &Installation,
+    transaction: &mut Transaction
+) -> color_eyre::Result<()> {
+    let user_js = configured_user_js()?;
+
     let mut found_profile = false;
     for data_folder in &installation.data_folders {
-        match firefox::common::debloat::<Zen>(
+        let profiles = firefox::common::debloat::<Zen>(
             data_folder,
-            resource::get_better_zen_user_js()?,
-            &custom_overrides.join("\n")
-        ) {
-            Err(why) => {
-                warn!(err = ?why, "Failed to debloat data folder: {}", data_folder.display());
-            }
-            Ok(profiles) => {
-                found_profile |= !profiles.is_empty();
-                debug!(data_folder = %data_folder.display(), "Successfully debloated data folder");
-            }
-        }
+            installation,
+            &user_js,
+            &|_| Ok(Vec::new()),
+            transaction
+        )
+        .wrap_err_with(|| format!("Failed to debloat data folder: {}", data_folder.display()))?;
+
+        found_profile |= !profiles.is_empty();
+        debug!(data_folder = %data_folder.display(), "Successfully debloated data folder");
     }
 
     if !found_profile {
         warn!("Failed to find any valid profiles in Zen installation");
     }
 
-    Ok(())
+    firefox::common::app_defaults::install(installation, &user_js)
+        .wrap_err("Failed to install app defaults")
 }